@@ -0,0 +1,9 @@
+-- Keyword index over chunk text. The rowid of each entry is the chunk id, and
+-- rows are kept in sync by the application when chunks are saved or deleted.
+CREATE VIRTUAL TABLE chunk_fts USING fts5(
+    data,
+    bookId UNINDEXED,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+INSERT INTO chunk_fts (rowid, data, bookId)
+SELECT id, data, bookId FROM chunk_data;
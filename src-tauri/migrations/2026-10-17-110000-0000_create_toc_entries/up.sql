@@ -0,0 +1,13 @@
+-- Your SQL goes here
+CREATE TABLE toc_entries (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    book_id INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    parent_position INTEGER,
+    level INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    page_number INTEGER,
+    href TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX toc_entries_book_id ON toc_entries (book_id, position);
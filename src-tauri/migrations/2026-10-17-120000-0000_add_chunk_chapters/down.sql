@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX chunk_data_book_chapter;
+ALTER TABLE chunk_data DROP COLUMN endOffset;
+ALTER TABLE chunk_data DROP COLUMN startOffset;
+ALTER TABLE chunk_data DROP COLUMN href;
+ALTER TABLE chunk_data DROP COLUMN chapterId;
\ No newline at end of file
@@ -0,0 +1,8 @@
+-- Where each chunk sits in the book: the table of contents entry it belongs
+-- to (its position in toc_entries), the spine document it came from, and its
+-- character range in that page or document. Chunks saved earlier keep NULLs.
+ALTER TABLE chunk_data ADD COLUMN chapterId INTEGER;
+ALTER TABLE chunk_data ADD COLUMN href TEXT;
+ALTER TABLE chunk_data ADD COLUMN startOffset INTEGER;
+ALTER TABLE chunk_data ADD COLUMN endOffset INTEGER;
+CREATE INDEX chunk_data_book_chapter ON chunk_data (bookId, chapterId);
@@ -0,0 +1,15 @@
+-- One ingestion job per book. `last_page` is the last page (or spine
+-- document) whose vectors are known to be on disk; an interrupted job
+-- resumes after it.
+CREATE TABLE ingest_jobs (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    book_id INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    total_pages INTEGER NOT NULL DEFAULT 0,
+    done_pages INTEGER NOT NULL DEFAULT 0,
+    last_page INTEGER,
+    error TEXT,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+CREATE UNIQUE INDEX ingest_jobs_book_id ON ingest_jobs (book_id);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX books_identifier;
+DROP INDEX books_content_hash;
+ALTER TABLE books DROP COLUMN identifier;
+ALTER TABLE books DROP COLUMN content_hash;
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- What a book is, independent of where its file lives: the SHA-256 of the
+-- file and, for EPUBs, the publisher's identifier (ISBN or UUID). Books
+-- saved earlier get their hash when the app next starts.
+ALTER TABLE books ADD COLUMN content_hash TEXT;
+ALTER TABLE books ADD COLUMN identifier TEXT;
+CREATE INDEX books_content_hash ON books (content_hash);
+CREATE INDEX books_identifier ON books (identifier);
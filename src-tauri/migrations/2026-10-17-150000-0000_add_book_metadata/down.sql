@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE books DROP COLUMN contributors;
+ALTER TABLE books DROP COLUMN published;
+ALTER TABLE books DROP COLUMN series_index;
+ALTER TABLE books DROP COLUMN series;
+ALTER TABLE books DROP COLUMN subjects;
+ALTER TABLE books DROP COLUMN description;
+ALTER TABLE books DROP COLUMN identifiers;
+ALTER TABLE books DROP COLUMN language;
\ No newline at end of file
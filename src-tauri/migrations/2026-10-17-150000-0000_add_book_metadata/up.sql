@@ -0,0 +1,10 @@
+-- Descriptive metadata read from the book. identifiers, subjects and
+-- contributors are JSON arrays.
+ALTER TABLE books ADD COLUMN language TEXT;
+ALTER TABLE books ADD COLUMN identifiers TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE books ADD COLUMN description TEXT;
+ALTER TABLE books ADD COLUMN subjects TEXT NOT NULL DEFAULT '[]';
+ALTER TABLE books ADD COLUMN series TEXT;
+ALTER TABLE books ADD COLUMN series_index REAL;
+ALTER TABLE books ADD COLUMN published TEXT;
+ALTER TABLE books ADD COLUMN contributors TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,7 @@
+-- Books whose table of contents has been read from their file, so that a
+-- book without one is not read again each time it is asked for.
+CREATE TABLE toc_extractions (
+    book_id INTEGER NOT NULL PRIMARY KEY,
+    created_at DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO toc_extractions (book_id) SELECT DISTINCT book_id FROM toc_entries;
@@ -1,7 +1,10 @@
+use crate::http;
+
 #[tauri::command]
 pub async fn get_realtime_client_secret() -> Result<String, String> {
-    let url = "https://rishi-worker.faridmato90.workers.dev/api/realtime/client_secrets";
-    let client_secret = reqwest::get(url)
+    let client = http::api_client().map_err(|e| e.to_string())?;
+    let client_secret = client
+        .send(client.get("/api/realtime/client_secrets"))
         .await
         .map_err(|e| e.to_string())?
         .text()
@@ -14,9 +17,13 @@ pub async fn get_realtime_client_secret() -> Result<String, String> {
 mod tests {
     use super::*;
 
+    use crate::test_worker::MOCK_CLIENT_SECRET;
+    use expectest::prelude::*;
+
     #[tokio::test]
     async fn test_get_realtime_client_secret() {
         let client_secret = get_realtime_client_secret().await.unwrap();
         println!("Client secret: {}", client_secret);
+        expect!(client_secret.as_str()).to(be_equal_to(MOCK_CLIENT_SECRET));
     }
 }
@@ -0,0 +1,598 @@
+// Splits book text into overlapping passages for indexing.
+//
+// A book is a list of sections: EPUB spine documents (numbered from 0, as
+// the reader numbers them) or PDF pages (numbered from 1). Sections are split
+// on paragraph and sentence boundaries into chunks of at most
+// `max_tokens` tokens, where a token is a whitespace-separated word.
+
+use std::path::Path;
+
+use epub::doc::EpubDoc;
+use serde::{Deserialize, Serialize};
+
+use crate::sql::ChunkDataInsertable;
+
+/// Ids stay within the integers JavaScript can represent exactly.
+const MAX_SAFE_ID: u64 = (1 << 53) - 1;
+
+/// Elements that start a new paragraph in the extracted text.
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "td",
+    "th",
+    "tr",
+    "ul",
+];
+
+/// Elements whose content is never text of the book.
+const SKIPPED_TAGS: &[&str] = &["head", "script", "style", "svg"];
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ChunkerOptions {
+    /// Upper bound on the tokens of a chunk. Only a single sentence longer
+    /// than this is ever split mid-sentence.
+    pub max_tokens: usize,
+    /// How many tokens of whole sentences at the end of a chunk are repeated
+    /// at the start of the next one.
+    pub overlap_tokens: usize,
+}
+
+impl Default for ChunkerOptions {
+    fn default() -> Self {
+        Self {
+            max_tokens: 200,
+            overlap_tokens: 40,
+        }
+    }
+}
+
+/// The text of one spine document or PDF page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Section {
+    pub page_number: i32,
+    pub text: String,
+    /// Path of the spine document inside the EPUB.
+    pub href: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Chunk {
+    pub id: i64,
+    pub book_id: i32,
+    pub page_number: i32,
+    pub text: String,
+    /// Where the chunk is, as in [`ChunkDataInsertable`]; `chapter_id` is
+    /// only known once the table of contents has been read.
+    pub start: usize,
+    pub end: usize,
+    pub href: Option<String>,
+    pub chapter_id: Option<i32>,
+}
+
+impl From<Chunk> for ChunkDataInsertable {
+    fn from(chunk: Chunk) -> Self {
+        Self {
+            id: Some(chunk.id),
+            page_number: chunk.page_number,
+            book_id: chunk.book_id,
+            data: chunk.text,
+            chapter_id: chunk.chapter_id,
+            href: chunk.href,
+            start_offset: Some(chunk.start as i32),
+            end_offset: Some(chunk.end as i32),
+        }
+    }
+}
+
+/// A sentence, or a window of words of an over-long sentence, as a byte
+/// range of the section text.
+struct Unit {
+    start: usize,
+    end: usize,
+    tokens: usize,
+    starts_paragraph: bool,
+}
+
+/// An id derived from where the chunk is, so chunking the same text again
+/// produces the same ids and re-ingestion replaces rows instead of
+/// duplicating them.
+pub fn chunk_id(book_id: i32, page_number: i32, start: usize, end: usize) -> i64 {
+    let digest = md5::compute(format!("{}-{}-{}-{}", book_id, page_number, start, end));
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&digest.0[..8]);
+    (u64::from_be_bytes(bytes) & MAX_SAFE_ID) as i64
+}
+
+pub fn chunk_sections(book_id: i32, sections: &[Section], options: &ChunkerOptions) -> Vec<Chunk> {
+    sections
+        .iter()
+        .flat_map(|section| chunk_section(book_id, section, options))
+        .collect()
+}
+
+pub fn chunk_section(book_id: i32, section: &Section, options: &ChunkerOptions) -> Vec<Chunk> {
+    let max_tokens = options.max_tokens.max(1);
+    let units = units(&section.text, max_tokens);
+
+    let mut ranges = Vec::new();
+    let mut first = 0;
+    let mut tokens = 0;
+    for (i, unit) in units.iter().enumerate() {
+        let is_full = tokens + unit.tokens > max_tokens;
+        // Prefer ending a reasonably filled chunk where a paragraph ends.
+        let at_paragraph = unit.starts_paragraph && tokens >= max_tokens / 2;
+        if i > first && (is_full || at_paragraph) {
+            ranges.push((units[first].start, units[i - 1].end));
+
+            // Carry trailing sentences over, but never the whole chunk.
+            let mut next = i;
+            let mut overlap = 0;
+            while next > first + 1 && overlap + units[next - 1].tokens <= options.overlap_tokens {
+                next -= 1;
+                overlap += units[next].tokens;
+            }
+            first = next;
+            tokens = overlap;
+            if tokens + unit.tokens > max_tokens {
+                first = i;
+                tokens = 0;
+            }
+        }
+        tokens += unit.tokens;
+    }
+    if first < units.len() {
+        ranges.push((units[first].start, units[units.len() - 1].end));
+    }
+
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let start_char = section.text[..start].chars().count();
+            let end_char = start_char + section.text[start..end].chars().count();
+            Chunk {
+                id: chunk_id(book_id, section.page_number, start_char, end_char),
+                book_id,
+                page_number: section.page_number,
+                text: section.text[start..end].to_string(),
+                start: start_char,
+                end: end_char,
+                href: section.href.clone(),
+                chapter_id: None,
+            }
+        })
+        .collect()
+}
+
+fn units(text: &str, max_tokens: usize) -> Vec<Unit> {
+    let mut units = Vec::new();
+    for (paragraph_start, paragraph_end) in paragraphs(text) {
+        let paragraph = &text[paragraph_start..paragraph_end];
+        let mut starts_paragraph = true;
+        for (sentence_start, sentence_end) in sentences(paragraph) {
+            let offset = paragraph_start + sentence_start;
+            let sentence = &paragraph[sentence_start..sentence_end];
+            for (start, end, tokens) in word_windows(sentence, max_tokens) {
+                units.push(Unit {
+                    start: offset + start,
+                    end: offset + end,
+                    tokens,
+                    starts_paragraph,
+                });
+                starts_paragraph = false;
+            }
+        }
+    }
+    units
+}
+
+/// Byte ranges of runs of non-blank lines.
+fn paragraphs(text: &str) -> Vec<(usize, usize)> {
+    let mut paragraphs = Vec::new();
+    let mut current: Option<(usize, usize)> = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let line_start = offset;
+        offset += line.len();
+        if line.trim().is_empty() {
+            paragraphs.extend(current.take());
+            continue;
+        }
+        let (start, end) = trimmed(text, line_start, offset);
+        current = Some(match current {
+            Some((paragraph_start, _)) => (paragraph_start, end),
+            None => (start, end),
+        });
+    }
+    paragraphs.extend(current);
+    paragraphs
+}
+
+/// Byte ranges of the sentences of a paragraph: a sentence ends at `.`, `!`
+/// or `?`, optionally followed by closing quotes or brackets, and then
+/// whitespace.
+fn sentences(paragraph: &str) -> Vec<(usize, usize)> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut after_terminator = false;
+    for (i, c) in paragraph.char_indices() {
+        if matches!(c, '.' | '!' | '?' | '…') {
+            after_terminator = true;
+        } else if after_terminator && c.is_whitespace() {
+            sentences.push(trimmed(paragraph, start, i));
+            start = i;
+            after_terminator = false;
+        } else if !matches!(c, '"' | '\'' | '”' | '’' | ')' | ']' | '»') {
+            after_terminator = false;
+        }
+    }
+    sentences.push(trimmed(paragraph, start, paragraph.len()));
+    sentences.retain(|(start, end)| start < end);
+    sentences
+}
+
+/// Byte ranges and token counts of consecutive windows of at most
+/// `max_tokens` words.
+fn word_windows(sentence: &str, max_tokens: usize) -> Vec<(usize, usize, usize)> {
+    let mut words = Vec::new();
+    let mut word_start = None;
+    for (i, c) in sentence.char_indices() {
+        match (c.is_whitespace(), word_start) {
+            (true, Some(start)) => {
+                words.push((start, i));
+                word_start = None;
+            }
+            (false, None) => word_start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(start) = word_start {
+        words.push((start, sentence.len()));
+    }
+
+    words
+        .chunks(max_tokens)
+        .map(|window| (window[0].0, window[window.len() - 1].1, window.len()))
+        .collect()
+}
+
+fn trimmed(text: &str, start: usize, end: usize) -> (usize, usize) {
+    let slice = &text[start..end];
+    let leading = slice.len() - slice.trim_start().len();
+    let trailing = slice.len() - slice.trim_end().len();
+    if leading == slice.len() {
+        return (start, start);
+    }
+    (start + leading, end - trailing)
+}
+
+/// The text of each spine document of an EPUB, in reading order.
+pub fn epub_sections(path: &Path) -> anyhow::Result<Vec<Section>> {
+    let mut doc = EpubDoc::new(path).map_err(|e| anyhow::anyhow!("Failed to open EPUB: {}", e))?;
+    let idrefs: Vec<String> = doc.spine.iter().map(|item| item.idref.clone()).collect();
+
+    let mut sections = Vec::new();
+    for (index, idref) in idrefs.iter().enumerate() {
+        let href = doc
+            .resources
+            .get(idref)
+            .map(|resource| resource.path.to_string_lossy().replace('\\', "/"));
+        let Some((html, _)) = doc.get_resource_str(idref) else {
+            continue;
+        };
+        let text = html_to_text(&html);
+        if !text.is_empty() {
+            sections.push(Section {
+                page_number: index as i32,
+                text,
+                href,
+            });
+        }
+    }
+    Ok(sections)
+}
+
+/// Visible text of an (X)HTML document, with block elements separated by
+/// blank lines and other whitespace collapsed.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    let mut skipping: Option<String> = None;
+    let mut rest = html;
+    while let Some(tag_start) = rest.find('<') {
+        if skipping.is_none() {
+            push_text(&mut text, &rest[..tag_start]);
+        }
+        rest = &rest[tag_start..];
+        let tag_end = if rest.starts_with("<!--") {
+            rest.find("-->").map(|end| end + 3)
+        } else {
+            rest.find('>').map(|end| end + 1)
+        };
+        let Some(tag_end) = tag_end else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..tag_end - 1];
+        rest = &rest[tag_end..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        // Local names, so `<xhtml:p>` counts as a paragraph.
+        let name = name.rsplit(':').next().unwrap_or_default().to_string();
+
+        if let Some(skipped) = &skipping {
+            if closing && *skipped == name {
+                skipping = None;
+            }
+            continue;
+        }
+        if SKIPPED_TAGS.contains(&name.as_str()) && !closing && !tag.ends_with('/') {
+            skipping = Some(name);
+        } else if name == "br" {
+            text.push('\n');
+        } else if BLOCK_TAGS.contains(&name.as_str()) {
+            text.push_str("\n\n");
+        }
+    }
+    if skipping.is_none() {
+        push_text(&mut text, rest);
+    }
+
+    text.split("\n\n")
+        .map(|paragraph| {
+            paragraph
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .filter(|paragraph| !paragraph.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Append character data, decoding entities and collapsing whitespace.
+fn push_text(text: &mut String, raw: &str) {
+    let decoded = decode_entities(raw);
+    for c in decoded.chars() {
+        if c.is_whitespace() && c != '\u{a0}' {
+            if !text.ends_with([' ', '\n']) && !text.is_empty() {
+                text.push(' ');
+            }
+        } else if c == '\u{a0}' {
+            text.push(' ');
+        } else {
+            text.push(c);
+        }
+    }
+}
+
+fn decode_entities(raw: &str) -> String {
+    let mut decoded = String::with_capacity(raw.len());
+    let mut rest = raw;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| Some((decode_entity(&rest[1..end])?, end)));
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+fn decode_entity(name: &str) -> Option<char> {
+    if let Some(hex) = name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+        return u32::from_str_radix(hex, 16).ok().and_then(char::from_u32);
+    }
+    if let Some(decimal) = name.strip_prefix('#') {
+        return decimal.parse().ok().and_then(char::from_u32);
+    }
+    Some(match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        "nbsp" => '\u{a0}',
+        "mdash" => '—',
+        "ndash" => '–',
+        "hellip" => '…',
+        "lsquo" => '‘',
+        "rsquo" => '’',
+        "ldquo" => '“',
+        "rdquo" => '”',
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use expectest::prelude::*;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn section(text: &str) -> Section {
+        Section {
+            page_number: 3,
+            text: text.to_string(),
+            href: None,
+        }
+    }
+
+    fn options(max_tokens: usize, overlap_tokens: usize) -> ChunkerOptions {
+        ChunkerOptions {
+            max_tokens,
+            overlap_tokens,
+        }
+    }
+
+    #[test]
+    fn test_chunks_split_on_sentences_and_overlap() {
+        let text = "One two three. Four five six. Seven eight nine. Ten eleven twelve.";
+        let chunks = chunk_section(1, &section(text), &options(6, 3));
+
+        let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "One two three. Four five six.",
+                "Four five six. Seven eight nine.",
+                "Seven eight nine. Ten eleven twelve.",
+            ]
+        );
+        for chunk in &chunks {
+            let at_offsets: String = text
+                .chars()
+                .skip(chunk.start)
+                .take(chunk.end - chunk.start)
+                .collect();
+            expect!(at_offsets).to(be_equal_to(chunk.text.clone()));
+            expect!(chunk.page_number).to(be_equal_to(3));
+        }
+    }
+
+    #[test]
+    fn test_chunks_end_at_paragraphs_and_offsets_count_characters() {
+        let text = "Café au lait. Crème brûlée.\n\nÉclair tout court.";
+        let chunks = chunk_section(1, &section(text), &options(8, 0));
+
+        expect!(chunks.len()).to(be_equal_to(2));
+        expect!(chunks[1].text.as_str()).to(be_equal_to("Éclair tout court."));
+        expect!(chunks[1].start).to(be_equal_to(29));
+        expect!(chunks[1].end).to(be_equal_to(text.chars().count()));
+    }
+
+    #[test]
+    fn test_long_sentences_are_split_into_word_windows() {
+        let chunks = chunk_section(1, &section("a b c d e f g"), &options(3, 0));
+
+        let texts: Vec<&str> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+        assert_eq!(texts, vec!["a b c", "d e f", "g"]);
+    }
+
+    #[test]
+    fn test_ids_are_stable_and_distinct() {
+        let text = "First sentence here. Second sentence here.";
+        let once = chunk_section(7, &section(text), &options(3, 0));
+        let again = chunk_section(7, &section(text), &options(3, 0));
+        let other_book = chunk_section(8, &section(text), &options(3, 0));
+
+        expect!(once.clone()).to(be_equal_to(again));
+        expect!(once[0].id).not_to(be_equal_to(once[1].id));
+        expect!(once[0].id).not_to(be_equal_to(other_book[0].id));
+        expect!(once[0].id as u64 <= MAX_SAFE_ID).to(be_true());
+    }
+
+    #[test]
+    fn test_html_to_text_keeps_paragraphs_and_drops_markup() {
+        let html = r#"<?xml version="1.0"?><html><head><title>Ch. 1</title>
+            <style>p { color: red; }</style></head>
+            <body><h1>Chapter&nbsp;1</h1>
+            <p>Call me <em>Ishmael</em>.   Some years ago&#8212;never mind
+            how long.</p><!-- a <p> in a comment --><p>Fish &amp; chips<br/>and tea.</p>
+            </body></html>"#;
+
+        assert_eq!(
+            html_to_text(html),
+            "Chapter 1\n\nCall me Ishmael. Some years ago—never mind how long.\n\nFish & chips\nand tea."
+        );
+    }
+
+    #[test]
+    fn test_epub_sections_follow_the_spine() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("book.epub");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        let files = [
+            ("mimetype", "application/epub+zip"),
+            (
+                "META-INF/container.xml",
+                r#"<?xml version="1.0"?><container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#,
+            ),
+            (
+                "OEBPS/content.opf",
+                r#"<?xml version="1.0"?><package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id"><metadata xmlns:dc="http://purl.org/dc/elements/1.1/"><dc:title>Test</dc:title><dc:identifier id="id">test</dc:identifier></metadata><manifest><item id="one" href="one.xhtml" media-type="application/xhtml+xml"/><item id="two" href="two.xhtml" media-type="application/xhtml+xml"/></manifest><spine><itemref idref="two"/><itemref idref="one"/></spine></package>"#,
+            ),
+            (
+                "OEBPS/one.xhtml",
+                "<html><body><p>Chapter one.</p></body></html>",
+            ),
+            (
+                "OEBPS/two.xhtml",
+                "<html><body><p>Chapter two.</p></body></html>",
+            ),
+        ];
+        for (name, content) in files {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+
+        let sections = epub_sections(&path).unwrap();
+
+        assert_eq!(
+            sections,
+            vec![
+                Section {
+                    page_number: 0,
+                    text: "Chapter two.".to_string(),
+                    href: Some("OEBPS/two.xhtml".to_string()),
+                },
+                Section {
+                    page_number: 1,
+                    text: "Chapter one.".to_string(),
+                    href: Some("OEBPS/one.xhtml".to_string()),
+                },
+            ]
+        );
+    }
+}
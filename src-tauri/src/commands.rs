@@ -4,26 +4,35 @@ use std::io;
 use std::path::{Path, PathBuf};
 use zip::ZipArchive;
 // At the top of commands.rs
+use crate::conversation;
 use crate::embed::EmbedResult;
-use crate::embed::{embed_text, EmbedParam};
+use crate::embed::{embed_text, embedding_service, EmbedParam, ModelStatus};
 use crate::epub::Epub;
+use crate::http;
+use crate::llm::{self, AnswerStreamEvent, CitedAnswer};
 use crate::pdf::Pdf;
-use crate::shared::books::store_book_data;
 use crate::shared::books::Extractable;
 use crate::shared::types::BookData;
 use crate::sql;
-use crate::sql::ChunkDataInsertable;
+use crate::sql::{Book, BookInsertable, ChunkDataInsertable, RetrievedPassage};
 use crate::user::User;
-use crate::vectordb::{self, SearchResult, Vector};
+use crate::vectordb::{self, IndexConfig, Metric, SearchResult, Vector};
 use serde_json::json;
+use tauri::ipc::Channel;
 use tauri::Manager;
 use tauri_plugin_store::StoreExt;
 
+/// Read an EPUB's details and add it to the library, or find it there if
+/// it was added before.
 #[tauri::command]
 pub fn get_book_data(app: tauri::AppHandle, path: &Path) -> Result<BookData, String> {
-    let data = Epub::new(path);
-    store_book_data(app, &data).map_err(|e| e.to_string())?;
-    data.extract().map_err(|e| e.to_string())
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
+    let data = Epub::new(path).extract().map_err(|e| e.to_string())?;
+    sql::save_book_data(data.clone(), &app_data_dir)?;
+    Ok(data)
 }
 
 #[tauri::command]
@@ -39,17 +48,64 @@ pub async fn process_job(
         .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
     sql::process_job(page_number, book_id, page_data, &app_data_dir).await
 }
+/// Extract, chunk and index a book's text without the reader being open.
+#[tauri::command]
+pub async fn index_book_text(app: tauri::AppHandle, book_id: i32) -> Result<(), String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
+    sql::index_book_text(book_id, &app_data_dir).await
+}
+
+#[tauri::command]
+pub fn save_book(app: tauri::AppHandle, book: BookInsertable) -> Result<Book, String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
+    sql::save_book(book, &app_data_dir)
+}
+
+#[tauri::command]
+pub fn delete_book(app: tauri::AppHandle, book_id: i32) -> Result<(), String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
+    sql::delete_book(book_id, &app_data_dir)
+}
+
+#[tauri::command]
+pub fn book_needs_reindex(app: tauri::AppHandle, book_id: i32) -> Result<bool, String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
+    sql::book_needs_reindex(book_id, &app_data_dir)
+}
+
+#[tauri::command]
+pub async fn reindex_book(app: tauri::AppHandle, book_id: i32) -> Result<(), String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
+    sql::reindex_book(book_id, &app_data_dir).await
+}
+
 #[tauri::command]
 pub async fn poll_for_user(state: &str, timeout_sec: u64) -> Result<User, String> {
-    let worker_url = "https://rishi-worker.faridmato90.workers.dev";
     let path = format!("/api/user/{}", state);
-    let url = format!("{}{}", worker_url, path);
 
-    let client = reqwest::Client::new();
+    let client = http::api_client().map_err(|e| e.to_string())?;
     let mut elapsed = 0;
     let interval = 2; // seconds
     while elapsed < timeout_sec {
-        let response = client.get(&url).send().await.map_err(|e| e.to_string())?;
+        let response = client
+            .send(client.get(&path))
+            .await
+            .map_err(|e| e.to_string())?;
         if response.status().is_success() {
             let user: User = response.json::<User>().await.map_err(|e| e.to_string())?;
             return Ok(user);
@@ -60,18 +116,162 @@ pub async fn poll_for_user(state: &str, timeout_sec: u64) -> Result<User, String
     Err("Timeout reached while polling for user ID".to_string())
 }
 
+/// Retrieval only looks up to the reader's current position unless the
+/// question opts out with `spoiler_safe: false`.
+fn retrieval_limit(book_id: u32, spoiler_safe: Option<bool>) -> Result<Option<i32>, String> {
+    sql::retrieval_limit(book_id as i32, spoiler_safe.unwrap_or(true))
+}
+
 #[tauri::command]
 pub async fn get_context_for_query(
     app: tauri::AppHandle,
     query_text: String,
     book_id: u32,
     k: usize,
-) -> Result<Vec<String>, String> {
+    spoiler_safe: Option<bool>,
+) -> Result<Vec<RetrievedPassage>, String> {
     let app_data_dir = app
         .path()
         .app_data_dir()
         .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
-    sql::get_context_for_query(query_text, book_id, &app_data_dir, k).await
+    let max_page = retrieval_limit(book_id, spoiler_safe)?;
+    sql::get_context_for_query(query_text, book_id, &app_data_dir, k, max_page).await
+}
+
+/// Answer a question about a book from its most relevant passages, with the
+/// answer's citations resolved to chunks and pages.
+#[tauri::command]
+pub async fn answer_question(
+    app: tauri::AppHandle,
+    question: String,
+    book_id: u32,
+    k: Option<usize>,
+    spoiler_safe: Option<bool>,
+) -> Result<CitedAnswer, String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
+    let max_page = retrieval_limit(book_id, spoiler_safe)?;
+    let passages = sql::get_context_for_query(
+        question.clone(),
+        book_id,
+        &app_data_dir,
+        k.unwrap_or(5),
+        max_page,
+    )
+    .await?;
+    llm::get_cited_answer(&question, passages, &[])
+        .await
+        .map_err(|e| format!("Failed to get answer: {}", e))
+}
+
+/// Ask a question in a conversation. Earlier messages are replayed to the
+/// model and follow-ups are rewritten into standalone retrieval queries;
+/// the exchange is saved to the conversation.
+#[tauri::command]
+pub async fn ask_in_conversation(
+    app: tauri::AppHandle,
+    conversation_id: i32,
+    question: String,
+    k: Option<usize>,
+    spoiler_safe: Option<bool>,
+) -> Result<CitedAnswer, String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
+    let turn = conversation::prepare_turn(conversation_id, &question).await?;
+    let max_page = retrieval_limit(turn.book_id, spoiler_safe)?;
+    let passages = sql::get_context_for_query(
+        turn.query,
+        turn.book_id,
+        &app_data_dir,
+        k.unwrap_or(5),
+        max_page,
+    )
+    .await?;
+    let answer = llm::get_cited_answer(&question, passages, &turn.history)
+        .await
+        .map_err(|e| format!("Failed to get answer: {}", e))?;
+    conversation::record_exchange(conversation_id, &question, &answer)?;
+    Ok(answer)
+}
+
+/// Stream an answer to `on_event` as it is generated. Starting a new stream
+/// for `session_id` cancels the one already running there. With a
+/// `conversation_id` the question is asked in that conversation, as in
+/// [`ask_in_conversation`], and the finished exchange is saved to it.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn stream_answer(
+    app: tauri::AppHandle,
+    session_id: String,
+    question: String,
+    book_id: u32,
+    conversation_id: Option<i32>,
+    k: Option<usize>,
+    spoiler_safe: Option<bool>,
+    on_event: Channel<AnswerStreamEvent>,
+) -> Result<(), String> {
+    let mut stream = llm::begin_stream(&session_id);
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
+
+    let answer = async {
+        let turn = match conversation_id {
+            Some(conversation_id) => conversation::prepare_turn(conversation_id, &question).await?,
+            None => conversation::Turn {
+                book_id,
+                query: question.clone(),
+                history: Vec::new(),
+            },
+        };
+        let max_page = retrieval_limit(turn.book_id, spoiler_safe)?;
+        let passages = sql::get_context_for_query(
+            turn.query,
+            turn.book_id,
+            &app_data_dir,
+            k.unwrap_or(5),
+            max_page,
+        )
+        .await?;
+        let _ = on_event.send(AnswerStreamEvent::Passages(passages.clone()));
+        let answer = llm::stream_cited_answer(&question, passages, &turn.history, |token| {
+            let _ = on_event.send(AnswerStreamEvent::Token(token.to_string()));
+        })
+        .await
+        .map_err(|e| format!("Failed to get answer: {}", e))?;
+        if let Some(conversation_id) = conversation_id {
+            conversation::record_exchange(conversation_id, &question, &answer)?;
+        }
+        Ok::<_, String>(answer)
+    };
+
+    tokio::select! {
+        result = answer => match result {
+            Ok(answer) => {
+                let _ = on_event.send(AnswerStreamEvent::Done(answer));
+                Ok(())
+            }
+            Err(e) => {
+                let _ = on_event.send(AnswerStreamEvent::Error(e.clone()));
+                Err(e)
+            }
+        },
+        _ = stream.cancelled() => {
+            let _ = on_event.send(AnswerStreamEvent::Cancelled);
+            Ok(())
+        }
+    }
+}
+
+/// Stop the answer being streamed for `session_id`, e.g. when the chat is closed.
+#[tauri::command]
+pub fn cancel_answer_stream(session_id: String) -> bool {
+    llm::cancel_stream(&session_id)
 }
 
 #[tauri::command]
@@ -80,6 +280,7 @@ pub fn save_vectors(
     name: &str,
     dim: usize,
     vectors: Vec<Vector>,
+    metric: Option<Metric>,
 ) -> Result<(), String> {
     if vectors.is_empty() {
         return Err("Vectors cannot be empty".to_string());
@@ -89,7 +290,16 @@ pub fn save_vectors(
         .app_data_dir()
         .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
 
-    vectordb::save_vectors(vectors, app_data_dir, dim, name).map_err(|e| e.to_string())
+    let config = IndexConfig {
+        metric,
+        ..Default::default()
+    };
+    vectordb::save_vectors(vectors, app_data_dir, dim, name, &config).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub fn flush_vectors() -> Result<(), String> {
+    vectordb::flush_vectors().map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -120,10 +330,12 @@ pub fn get_user_from_store(app: tauri::AppHandle) -> Result<User, String> {
 #[tauri::command]
 pub async fn get_user(app: tauri::AppHandle, user_id: &str) -> Result<User, String> {
     // /api/clerk/user/:userId from the worker
-    let worker_url = "https://rishi-worker.faridmato90.workers.dev";
     let path = format!("/api/clerk/user/{}", user_id);
-    let url = format!("{}{}", worker_url, path);
-    let response = reqwest::get(url).await.map_err(|e| e.to_string())?;
+    let client = http::api_client().map_err(|e| e.to_string())?;
+    let response = client
+        .send(client.get(&path))
+        .await
+        .map_err(|e| e.to_string())?;
     println!("response: {:?}", response);
     let user = response.json::<User>().await.map_err(|e| e.to_string())?;
     // save user to the database
@@ -146,13 +358,20 @@ pub fn search_vectors(
         .app_data_dir()
         .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
 
-    vectordb::search_vectors(app_data_dir, dim, name, query, k).map_err(|e| e.to_string())
+    vectordb::search_vectors(app_data_dir, dim, name, query, k, &IndexConfig::default())
+        .map_err(|e| e.to_string())
 }
+/// Read a PDF's details and add it to the library, or find it there if it
+/// was added before.
 #[tauri::command]
 pub fn get_pdf_data(app: tauri::AppHandle, path: &Path) -> Result<BookData, String> {
-    let data = Pdf::new(path);
-    store_book_data(app, &data).map_err(|e| e.to_string())?;
-    data.extract().map_err(|e| e.to_string())
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
+    let data = Pdf::new(path).extract().map_err(|e| e.to_string())?;
+    sql::save_book_data(data.clone(), &app_data_dir)?;
+    Ok(data)
 }
 
 #[tauri::command]
@@ -161,6 +380,11 @@ pub async fn embed(embedparams: Vec<EmbedParam>) -> Result<Vec<EmbedResult>, Str
     Ok(res)
 }
 
+#[tauri::command]
+pub fn get_embedding_model_status() -> ModelStatus {
+    embedding_service().status()
+}
+
 #[tauri::command]
 pub fn is_dev() -> bool {
     tauri::is_dev()
@@ -207,3 +431,24 @@ pub fn unzip(file_path: &str, out_dir: &str) -> Result<PathBuf, String> {
 
     Ok(output_dir.to_path_buf())
 }
+
+#[cfg(test)]
+mod tests {
+    use expectest::prelude::*;
+
+    use super::poll_for_user;
+    use crate::test_worker::PENDING_STATE_PREFIX;
+
+    #[tokio::test]
+    async fn test_poll_for_user_returns_the_signed_in_user() {
+        let user = poll_for_user("signed-in-state", 5).await.unwrap();
+        let user = serde_json::to_value(user).unwrap();
+        expect!(user["id"].as_str()).to(be_equal_to(Some("user_mock")));
+    }
+
+    #[tokio::test]
+    async fn test_poll_for_user_times_out_while_sign_in_is_pending() {
+        let state = format!("{}-state", PENDING_STATE_PREFIX);
+        expect!(poll_for_user(&state, 1).await.is_err()).to(be_true());
+    }
+}
@@ -0,0 +1,347 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::db::DB_POOL;
+use crate::llm::{self, ChatTurn, Citation, CitedAnswer, Role};
+use crate::models::{Conversations, Messages};
+use crate::schema::{conversations, messages};
+
+/// How many earlier messages are replayed into the prompt.
+const MAX_HISTORY_MESSAGES: usize = 10;
+/// Conversations are titled after their first question, cut to this length.
+const MAX_TITLE_CHARS: usize = 80;
+
+#[derive(Insertable)]
+#[diesel(table_name = conversations)]
+struct ConversationInsertable {
+    book_id: i32,
+    title: String,
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = messages)]
+struct MessageInsertable<'a> {
+    conversation_id: i32,
+    role: &'a str,
+    content: &'a str,
+    citations: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Conversation {
+    pub id: i32,
+    pub book_id: i32,
+    pub title: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    pub id: i32,
+    pub conversation_id: i32,
+    pub role: Role,
+    pub content: String,
+    pub citations: Vec<Citation>,
+}
+
+impl From<Conversations> for Conversation {
+    fn from(conversation: Conversations) -> Self {
+        Self {
+            id: conversation.id,
+            book_id: conversation.book_id,
+            title: conversation.title,
+        }
+    }
+}
+
+impl TryFrom<Messages> for Message {
+    type Error = String;
+
+    fn try_from(message: Messages) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: message.id,
+            conversation_id: message.conversation_id,
+            role: message.role.parse()?,
+            content: message.content,
+            citations: serde_json::from_str(&message.citations)
+                .map_err(|e| format!("Invalid citations for message {}: {}", message.id, e))?,
+        })
+    }
+}
+
+/// What a new question in a conversation needs: the book to search, a
+/// standalone retrieval query and the history to replay.
+pub struct Turn {
+    pub book_id: u32,
+    pub query: String,
+    pub history: Vec<ChatTurn>,
+}
+
+#[tauri::command]
+pub fn start_conversation(book_id: i32) -> Result<Conversation, String> {
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection: {}", e))?;
+
+    let conversation = diesel::insert_into(conversations::table)
+        .values(ConversationInsertable {
+            book_id,
+            title: String::new(),
+        })
+        .returning(Conversations::as_returning())
+        .get_result::<Conversations>(&mut conn)
+        .map_err(|e| format!("Failed to create conversation: {}", e))?;
+
+    Ok(conversation.into())
+}
+
+/// Conversations about a book, most recently active first.
+#[tauri::command]
+pub fn list_conversations(book_id: i32) -> Result<Vec<Conversation>, String> {
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection: {}", e))?;
+
+    let results = conversations::table
+        .filter(conversations::book_id.eq(&book_id))
+        .order_by((conversations::updated_at.desc(), conversations::id.desc()))
+        .select(Conversations::as_select())
+        .load::<Conversations>(&mut conn)
+        .map_err(|e| format!("Failed to query conversations: {}", e))?;
+
+    Ok(results.into_iter().map(Conversation::from).collect())
+}
+
+pub fn get_conversation(conversation_id: i32) -> Result<Option<Conversation>, String> {
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection: {}", e))?;
+
+    let result = conversations::table
+        .filter(conversations::id.eq(&conversation_id))
+        .select(Conversations::as_select())
+        .first::<Conversations>(&mut conn)
+        .optional()
+        .map_err(|e| format!("Failed to query conversation: {}", e))?;
+
+    Ok(result.map(Conversation::from))
+}
+
+/// All messages of a conversation in the order they were sent, for resuming it.
+#[tauri::command]
+pub fn get_conversation_messages(conversation_id: i32) -> Result<Vec<Message>, String> {
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection: {}", e))?;
+
+    let results = messages::table
+        .filter(messages::conversation_id.eq(&conversation_id))
+        .order_by(messages::id.asc())
+        .select(Messages::as_select())
+        .load::<Messages>(&mut conn)
+        .map_err(|e| format!("Failed to query messages: {}", e))?;
+
+    results.into_iter().map(Message::try_from).collect()
+}
+
+#[tauri::command]
+pub fn delete_conversation(conversation_id: i32) -> Result<(), String> {
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection: {}", e))?;
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::delete(messages::table.filter(messages::conversation_id.eq(&conversation_id)))
+            .execute(conn)?;
+        diesel::delete(conversations::table.filter(conversations::id.eq(&conversation_id)))
+            .execute(conn)?;
+        Ok(())
+    })
+    .map_err(|e| format!("Failed to delete conversation: {}", e))
+}
+
+/// Delete every conversation about a book, as part of deleting the book.
+pub fn delete_conversations_for_book(conn: &mut SqliteConnection, book_id: i32) -> QueryResult<()> {
+    let conversation_ids = conversations::table
+        .filter(conversations::book_id.eq(&book_id))
+        .select(conversations::id);
+    diesel::delete(messages::table.filter(messages::conversation_id.eq_any(conversation_ids)))
+        .execute(conn)?;
+    diesel::delete(conversations::table.filter(conversations::book_id.eq(&book_id)))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// The last few messages of a conversation, oldest first, to replay into
+/// the prompt.
+pub fn recent_history(conversation_id: i32) -> Result<Vec<ChatTurn>, String> {
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection: {}", e))?;
+
+    let mut results = messages::table
+        .filter(messages::conversation_id.eq(&conversation_id))
+        .order_by(messages::id.desc())
+        .limit(MAX_HISTORY_MESSAGES as i64)
+        .select((messages::role, messages::content))
+        .load::<(String, String)>(&mut conn)
+        .map_err(|e| format!("Failed to query messages: {}", e))?;
+    results.reverse();
+
+    results
+        .into_iter()
+        .map(|(role, content)| {
+            Ok(ChatTurn {
+                role: role.parse()?,
+                content,
+            })
+        })
+        .collect()
+}
+
+/// Load the history for a new question in a conversation and turn the
+/// question into a standalone query for retrieval.
+pub async fn prepare_turn(conversation_id: i32, question: &str) -> Result<Turn, String> {
+    let conversation = get_conversation(conversation_id)?
+        .ok_or_else(|| format!("Conversation {} not found", conversation_id))?;
+    let history = recent_history(conversation_id)?;
+    let query = llm::rewrite_follow_up_query(question, &history)
+        .await
+        .map_err(|e| format!("Failed to rewrite question: {}", e))?;
+    Ok(Turn {
+        book_id: conversation.book_id as u32,
+        query,
+        history,
+    })
+}
+
+/// Store a question and its answer. The first question also names the
+/// conversation.
+pub fn record_exchange(
+    conversation_id: i32,
+    question: &str,
+    answer: &CitedAnswer,
+) -> Result<(), String> {
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection: {}", e))?;
+
+    let citations = serde_json::to_string(&answer.citations)
+        .map_err(|e| format!("Failed to serialize citations: {}", e))?;
+    let title: String = question.trim().chars().take(MAX_TITLE_CHARS).collect();
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        diesel::insert_into(messages::table)
+            .values(&vec![
+                MessageInsertable {
+                    conversation_id,
+                    role: Role::User.as_str(),
+                    content: question,
+                    citations: "[]".to_string(),
+                },
+                MessageInsertable {
+                    conversation_id,
+                    role: Role::Assistant.as_str(),
+                    content: &answer.answer,
+                    citations,
+                },
+            ])
+            .execute(conn)?;
+        diesel::update(conversations::table.filter(conversations::id.eq(&conversation_id)))
+            .set(conversations::updated_at.eq(diesel::dsl::now))
+            .execute(conn)?;
+        diesel::update(
+            conversations::table
+                .filter(conversations::id.eq(&conversation_id))
+                .filter(conversations::title.eq("")),
+        )
+        .set(conversations::title.eq(&title))
+        .execute(conn)?;
+        Ok(())
+    })
+    .map_err(|e| format!("Failed to save messages: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::llm::{Citation, CitedAnswer, Role};
+    use crate::test_helpers::init_test_database_setup;
+    use expectest::prelude::*;
+
+    use super::{
+        delete_conversation, get_conversation_messages, list_conversations, recent_history,
+        record_exchange, start_conversation, MAX_HISTORY_MESSAGES,
+    };
+
+    fn answer(text: &str) -> CitedAnswer {
+        CitedAnswer {
+            answer: text.to_string(),
+            citations: vec![Citation {
+                passage: 1,
+                chunk_id: 7,
+                page_number: 42,
+                start: 0,
+                end: 3,
+            }],
+            passages: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_conversation_is_persisted_and_resumable() -> Result<(), String> {
+        let _setup = init_test_database_setup()?;
+        let book_id = 51;
+
+        let conversation = start_conversation(book_id)?;
+        record_exchange(conversation.id, "Who is Ahab?", &answer("The captain [1]."))?;
+
+        let conversations = list_conversations(book_id)?;
+        expect!(conversations.len()).to(be_equal_to(1));
+        expect!(conversations[0].title.as_str()).to(be_equal_to("Who is Ahab?"));
+
+        let messages = get_conversation_messages(conversation.id)?;
+        expect!(messages.len()).to(be_equal_to(2));
+        expect!(messages[0].role).to(be_equal_to(Role::User));
+        expect!(messages[1].role).to(be_equal_to(Role::Assistant));
+        expect!(messages[1].content.as_str()).to(be_equal_to("The captain [1]."));
+        expect!(messages[1].citations[0].page_number).to(be_equal_to(42));
+
+        delete_conversation(conversation.id)?;
+        expect!(list_conversations(book_id)?.is_empty()).to(be_true());
+        expect!(get_conversation_messages(conversation.id)?.is_empty()).to(be_true());
+        Ok(())
+    }
+
+    #[test]
+    fn test_history_is_bounded_and_chronological() -> Result<(), String> {
+        let _setup = init_test_database_setup()?;
+        let conversation = start_conversation(52)?;
+        for i in 0..MAX_HISTORY_MESSAGES {
+            record_exchange(
+                conversation.id,
+                &format!("question {}", i),
+                &answer(&format!("answer {}", i)),
+            )?;
+        }
+
+        let history = recent_history(conversation.id)?;
+        expect!(history.len()).to(be_equal_to(MAX_HISTORY_MESSAGES));
+        let last = MAX_HISTORY_MESSAGES - 1;
+        expect!(history[history.len() - 1].content.clone())
+            .to(be_equal_to(format!("answer {}", last)));
+        expect!(history[0].role).to(be_equal_to(Role::User));
+        expect!(history[0].content.clone()).to(be_equal_to(format!(
+            "question {}",
+            last + 1 - MAX_HISTORY_MESSAGES / 2
+        )));
+        Ok(())
+    }
+}
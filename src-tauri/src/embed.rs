@@ -1,16 +1,23 @@
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::thread;
 
-use embed_anything::embeddings::embed::{EmbedData, EmbedderBuilder};
+use embed_anything::embeddings::embed::{EmbedData, Embedder, EmbedderBuilder};
 use embed_anything::process_chunks;
 use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct Metadata {
     pub id: u64,
     pub page_number: usize,
     pub book_id: u32,
+    /// Where the chunk is, as in [`ChunkDataInsertable`](crate::sql::ChunkDataInsertable).
+    pub chapter_id: Option<i32>,
+    pub href: Option<String>,
+    pub start: Option<usize>,
+    pub end: Option<usize>,
 }
 
 impl From<HashMap<String, String>> for Metadata {
@@ -27,17 +34,33 @@ impl From<HashMap<String, String>> for Metadata {
                 .unwrap_or(&"0".to_string())
                 .parse()
                 .unwrap(),
+            chapter_id: data.get("chapter_id").and_then(|value| value.parse().ok()),
+            href: data.get("href").cloned(),
+            start: data.get("start").and_then(|value| value.parse().ok()),
+            end: data.get("end").and_then(|value| value.parse().ok()),
         }
     }
 }
 
 impl From<Metadata> for HashMap<String, String> {
     fn from(data: Metadata) -> Self {
-        HashMap::from([
+        let mut map = HashMap::from([
             ("id".to_string(), data.id.to_string()),
             ("page_number".to_string(), data.page_number.to_string()),
             ("book_id".to_string(), data.book_id.to_string()),
-        ])
+        ]);
+        let optional = [
+            ("chapter_id", data.chapter_id.map(|id| id.to_string())),
+            ("href", data.href),
+            ("start", data.start.map(|start| start.to_string())),
+            ("end", data.end.map(|end| end.to_string())),
+        ];
+        for (key, value) in optional {
+            if let Some(value) = value {
+                map.insert(key.to_string(), value);
+            }
+        }
+        map
     }
 }
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -68,14 +91,178 @@ pub struct EmbedParam {
     pub metadata: Metadata,
 }
 
-pub async fn embed_text(embedparams: Vec<EmbedParam>) -> Result<Vec<EmbedResult>, String> {
-    let embedding_model = Arc::new(
-        EmbedderBuilder::new()
-            .model_architecture("bert")
-            .model_id(Some("sentence-transformers/all-MiniLM-L6-v2"))
+/// Lifecycle of the shared embedding model, surfaced to the UI so the first
+/// question can show "model warming up" instead of appearing frozen.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", content = "error", rename_all = "camelCase")]
+pub enum ModelStatus {
+    NotLoaded,
+    Loading,
+    Ready,
+    Failed(String),
+}
+
+pub const EMBEDDING_MODEL_ID: &str = "sentence-transformers/all-MiniLM-L6-v2";
+const EMBEDDING_MODEL_ARCHITECTURE: &str = "bert";
+
+/// Upper bound on the number of texts coalesced into a single model call.
+const MAX_BATCH_SIZE: usize = 64;
+
+struct EmbedRequest {
+    params: Vec<EmbedParam>,
+    respond_to: oneshot::Sender<Result<Vec<EmbedResult>, String>>,
+}
+
+/// Long-lived embedding service.
+///
+/// The model lives on a dedicated thread that loads it once and then serves
+/// requests from a queue. Requests that arrive while a batch is being embedded
+/// are coalesced into the next model call, so concurrent callers
+/// (`commands::embed`, `sql::process_job`, `sql::get_context_for_query`) share
+/// both the warm model and the batch.
+pub struct EmbeddingService {
+    sender: mpsc::UnboundedSender<EmbedRequest>,
+    status: Arc<RwLock<ModelStatus>>,
+}
+
+static EMBEDDING_SERVICE: OnceLock<EmbeddingService> = OnceLock::new();
+
+pub fn embedding_service() -> &'static EmbeddingService {
+    EMBEDDING_SERVICE.get_or_init(EmbeddingService::start)
+}
+
+impl EmbeddingService {
+    fn start() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let status = Arc::new(RwLock::new(ModelStatus::NotLoaded));
+        let worker_status = status.clone();
+        thread::Builder::new()
+            .name("embedding-service".to_string())
+            .spawn(move || Self::run(receiver, worker_status))
+            .expect("Failed to spawn embedding service thread");
+        Self { sender, status }
+    }
+
+    pub fn status(&self) -> ModelStatus {
+        self.status
+            .read()
+            .map(|status| status.clone())
+            .unwrap_or_else(|e| ModelStatus::Failed(e.to_string()))
+    }
+
+    pub async fn embed(&self, params: Vec<EmbedParam>) -> Result<Vec<EmbedResult>, String> {
+        if params.is_empty() {
+            return Ok(Vec::new());
+        }
+        let (respond_to, response) = oneshot::channel();
+        self.sender
+            .send(EmbedRequest { params, respond_to })
+            .map_err(|_| "Embedding service has stopped".to_string())?;
+        response
+            .await
+            .map_err(|_| "Embedding service dropped the request".to_string())?
+    }
+
+    fn set_status(status: &RwLock<ModelStatus>, value: ModelStatus) {
+        if let Ok(mut status) = status.write() {
+            *status = value;
+        }
+    }
+
+    fn load_model(status: &RwLock<ModelStatus>) -> Result<Arc<Embedder>, String> {
+        Self::set_status(status, ModelStatus::Loading);
+        let model = EmbedderBuilder::new()
+            .model_architecture(EMBEDDING_MODEL_ARCHITECTURE)
+            .model_id(Some(EMBEDDING_MODEL_ID))
             .from_pretrained_hf()
-            .map_err(|e| e.to_string())?,
-    );
+            .map_err(|e| e.to_string());
+        match model {
+            Ok(model) => {
+                Self::set_status(status, ModelStatus::Ready);
+                Ok(Arc::new(model))
+            }
+            Err(e) => {
+                Self::set_status(status, ModelStatus::Failed(e.clone()));
+                Err(e)
+            }
+        }
+    }
+
+    fn run(mut receiver: mpsc::UnboundedReceiver<EmbedRequest>, status: Arc<RwLock<ModelStatus>>) {
+        let runtime = match tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        {
+            Ok(runtime) => runtime,
+            Err(e) => {
+                Self::set_status(&status, ModelStatus::Failed(e.to_string()));
+                return;
+            }
+        };
+
+        // Warm the model up straight away; a failed load is retried on the next batch.
+        let mut model = Self::load_model(&status).ok();
+
+        while let Some(first) = receiver.blocking_recv() {
+            let mut pending = first.params.len();
+            let mut batch = vec![first];
+            while pending < MAX_BATCH_SIZE {
+                match receiver.try_recv() {
+                    Ok(request) => {
+                        pending += request.params.len();
+                        batch.push(request);
+                    }
+                    Err(_) => break,
+                }
+            }
+
+            let embedding_model = match &model {
+                Some(embedding_model) => embedding_model.clone(),
+                None => match Self::load_model(&status) {
+                    Ok(embedding_model) => model.insert(embedding_model).clone(),
+                    Err(e) => {
+                        for request in batch {
+                            let _ = request.respond_to.send(Err(e.clone()));
+                        }
+                        continue;
+                    }
+                },
+            };
+
+            let params = batch
+                .iter()
+                .flat_map(|request| request.params.iter().cloned())
+                .collect::<Vec<_>>();
+            match runtime.block_on(embed_with_model(&embedding_model, &params)) {
+                Ok(mut results) => {
+                    for request in batch {
+                        let rest = results.split_off(request.params.len().min(results.len()));
+                        let _ = request.respond_to.send(Ok(results));
+                        results = rest;
+                    }
+                }
+                // One bad request shouldn't fail the others it was batched with.
+                Err(_) if batch.len() > 1 => {
+                    for request in batch {
+                        let result =
+                            runtime.block_on(embed_with_model(&embedding_model, &request.params));
+                        let _ = request.respond_to.send(result);
+                    }
+                }
+                Err(e) => {
+                    for request in batch {
+                        let _ = request.respond_to.send(Err(e.clone()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn embed_with_model(
+    embedding_model: &Arc<Embedder>,
+    embedparams: &[EmbedParam],
+) -> Result<Vec<EmbedResult>, String> {
     let chunks = embedparams
         .iter()
         .map(|p| p.text.clone())
@@ -86,7 +273,8 @@ pub async fn embed_text(embedparams: Vec<EmbedParam>) -> Result<Vec<EmbedResult>
         .collect::<Vec<_>>();
     println!(">>> Processing chunks");
 
-    let embeddings = process_chunks(&chunks, &metadata, &embedding_model, Some(1), None)
+    let batch_size = chunks.len().clamp(1, MAX_BATCH_SIZE);
+    let embeddings = process_chunks(&chunks, &metadata, embedding_model, Some(batch_size), None)
         .await
         .map_err(|e| {
             println!("error: {:#?}", e.to_string());
@@ -96,6 +284,15 @@ pub async fn embed_text(embedparams: Vec<EmbedParam>) -> Result<Vec<EmbedResult>
     let res = Arc::into_inner(embeddings).ok_or("Failed to get embeddings")?;
     Ok(res.into_iter().map(EmbedResult::from).collect::<Vec<_>>())
 }
+
+/// Start loading the embedding model in the background.
+pub fn warm_up() {
+    embedding_service();
+}
+
+pub async fn embed_text(embedparams: Vec<EmbedParam>) -> Result<Vec<EmbedResult>, String> {
+    embedding_service().embed(embedparams).await
+}
 // Object = $2
 
 // metadata: {id: 7271375624100750, pageNumber: 11, bookId: 1}
@@ -106,6 +303,33 @@ pub async fn embed_text(embedparams: Vec<EmbedParam>) -> Result<Vec<EmbedResult>
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_metadata_round_trips_through_a_string_map() {
+        let metadata = Metadata {
+            id: 42,
+            page_number: 3,
+            book_id: 7,
+            chapter_id: Some(2),
+            href: Some("OEBPS/three.xhtml".to_string()),
+            start: Some(120),
+            end: Some(480),
+        };
+        let legacy = Metadata {
+            chapter_id: None,
+            href: None,
+            start: None,
+            end: None,
+            ..metadata.clone()
+        };
+
+        let map: HashMap<String, String> = metadata.clone().into();
+        let legacy_map: HashMap<String, String> = legacy.clone().into();
+
+        assert_eq!(Metadata::from(map), metadata);
+        assert_eq!(legacy_map.len(), 3);
+        assert_eq!(Metadata::from(legacy_map), legacy);
+    }
+
     #[tokio::test]
     async fn test_embed_text() {
         let mut embedparams = vec![];
@@ -116,6 +340,7 @@ mod tests {
                     id: 7271375624100750,
                     page_number: 11,
                     book_id: 1,
+                    ..Default::default()
                 },
             });
         }
@@ -123,4 +348,27 @@ mod tests {
 
         assert!(!res.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_concurrent_embed_requests_share_the_model() {
+        let params = |page_number: usize| {
+            vec![EmbedParam {
+                text: format!("Text from page {}", page_number),
+                metadata: Metadata {
+                    id: page_number as u64,
+                    page_number,
+                    book_id: 1,
+                    ..Default::default()
+                },
+            }]
+        };
+        let (first, second) = tokio::join!(embed_text(params(1)), embed_text(params(2)));
+        let (first, second) = (first.unwrap(), second.unwrap());
+
+        assert_eq!(first.len(), 1);
+        assert_eq!(second.len(), 1);
+        assert_eq!(first[0].metadata.page_number, 1);
+        assert_eq!(second[0].metadata.page_number, 2);
+        assert_eq!(embedding_service().status(), ModelStatus::Ready);
+    }
 }
@@ -1,10 +1,13 @@
+use crate::chunker::html_to_text;
 use crate::shared::{
-    books::Extractable,
-    types::{BookData, BookKind},
+    books::{content_hash, create_placeholder_cover, Cover, Extractable, TocExtractable},
+    types::{BookData, BookKind, BookMetadata, Contributor, TocEntry},
 };
-use epub::doc::EpubDoc;
+use epub::doc::{EpubDoc, MetadataItem, NavPoint};
+use xml::reader::{EventReader, XmlEvent};
 
-use std::path::{Path, PathBuf};
+use std::io::{Read, Seek};
+use std::path::{Component, Path, PathBuf};
 
 pub struct Epub {
     pub path: PathBuf,
@@ -15,20 +18,29 @@ impl Epub {
             path: path.to_path_buf(),
         }
     }
+
+    /// The publisher's identifier for the book, without extracting the rest.
+    pub fn identifier(&self) -> Option<String> {
+        let doc = EpubDoc::new(&self.path).ok()?;
+        book_identifier(&doc)
+    }
 }
 impl Extractable for Epub {
     fn extract(&self) -> Result<BookData, Box<dyn std::error::Error>> {
         let epub_path = &self.path;
         let mut doc = EpubDoc::new(epub_path).map_err(|e| e.to_string())?;
-        let cover_data = doc.get_cover().ok_or("No cover found")?;
-        let cover = cover_data.0;
+        let cover = match doc.get_cover() {
+            Some((cover, _mime)) => Cover::Normal(cover),
+            None => create_placeholder_cover()?,
+        };
+        let cover_kind = Some(cover.to_string());
+        let (Cover::Normal(cover) | Cover::Fallback(cover)) = cover;
         let title = doc.get_title();
 
         let author = doc.mdata("creator").map(|data| data.value.clone());
         let publisher = doc.mdata("publisher").map(|data| data.value.clone());
-        // create a unique id by hashing the path
-        let digest = md5::compute(epub_path.to_string_lossy().to_string());
-        let id = format!("{:x}", digest);
+        let id = content_hash(epub_path)?;
+        let identifier = book_identifier(&doc);
         let file_path = epub_path.to_string_lossy().to_string();
         let kind = BookKind::Epub.to_string();
         let current_location = "".to_string();
@@ -42,7 +54,518 @@ impl Extractable for Epub {
             publisher,
             file_path,
             current_location,
-            None,
-        ))
+            cover_kind,
+        )
+        .with_identifier(identifier)
+        .with_metadata(book_metadata(&doc)))
+    }
+}
+
+/// Everything else the package metadata says about the book.
+fn book_metadata<R: Read + Seek>(doc: &EpubDoc<R>) -> BookMetadata {
+    let values = |property: &str| -> Vec<String> {
+        doc.metadata
+            .iter()
+            .filter(|item| item.property == property)
+            .map(|item| item.value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .collect()
+    };
+    let contributors = doc
+        .metadata
+        .iter()
+        .filter(|item| item.property == "creator" || item.property == "contributor")
+        .filter(|item| !item.value.trim().is_empty())
+        .map(|item| Contributor {
+            name: item.value.trim().to_string(),
+            role: item
+                .refinement("role")
+                .map(|role| role.value.trim().to_string()),
+        })
+        .collect();
+    let (series, series_index) = series(doc);
+
+    BookMetadata {
+        language: values("language").into_iter().next(),
+        identifiers: values("identifier"),
+        // Descriptions are often HTML.
+        description: values("description")
+            .first()
+            .map(|description| html_to_text(description))
+            .filter(|description| !description.is_empty()),
+        subjects: values("subject"),
+        series,
+        series_index,
+        published: publication_date(doc),
+        contributors,
+    }
+}
+
+/// The series the book is part of and its place in it, from an EPUB 3
+/// collection or, failing that, calibre's metadata.
+fn series<R: Read + Seek>(doc: &EpubDoc<R>) -> (Option<String>, Option<f64>) {
+    let collection = doc.metadata.iter().find(|item| {
+        item.property == "belongs-to-collection"
+            && item
+                .refinement("collection-type")
+                .is_none_or(|kind| kind.value.trim() == "series")
+    });
+    if let Some(collection) = collection {
+        let position = collection
+            .refinement("group-position")
+            .and_then(|position| position.value.trim().parse().ok());
+        return (Some(collection.value.trim().to_string()), position);
+    }
+
+    let series = doc
+        .mdata("calibre:series")
+        .map(|item| item.value.trim().to_string())
+        .filter(|series| !series.is_empty());
+    let index = series
+        .as_ref()
+        .and(doc.mdata("calibre:series_index"))
+        .and_then(|item| item.value.trim().parse().ok());
+    (series, index)
+}
+
+/// When the book was published. EPUB 2 books may list several dates told
+/// apart by `opf:event`; EPUB 3 only has the publication date.
+fn publication_date<R: Read + Seek>(doc: &EpubDoc<R>) -> Option<String> {
+    let dates: Vec<&MetadataItem> = doc
+        .metadata
+        .iter()
+        .filter(|item| item.property == "date")
+        .collect();
+    let event = |item: &MetadataItem| {
+        item.refinement("event")
+            .map(|event| event.value.trim().to_ascii_lowercase())
+    };
+    let date = dates
+        .iter()
+        .find(|item| event(item).as_deref() == Some("publication"))
+        .or_else(|| dates.iter().find(|item| event(item).is_none()))
+        .or(dates.first())?;
+    Some(date.value.trim().to_string()).filter(|date| !date.is_empty())
+}
+
+/// The book's ISBN if it declares one, as `urn:isbn:` with the ISBN-13,
+/// otherwise its unique identifier (often a `urn:uuid:`).
+fn book_identifier<R: Read + Seek>(doc: &EpubDoc<R>) -> Option<String> {
+    doc.metadata
+        .iter()
+        .filter(|item| item.property == "identifier")
+        .find_map(|item| {
+            // EPUB 2 puts the scheme in an `opf:scheme` attribute, EPUB 3
+            // in an `identifier-type` refinement.
+            let scheme = item
+                .refinement("scheme")
+                .or_else(|| item.refinement("identifier-type"))
+                .map(|refinement| refinement.value.as_str());
+            isbn(&item.value, scheme)
+        })
+        .or_else(|| {
+            doc.unique_identifier
+                .as_deref()
+                .map(str::trim)
+                .filter(|identifier| !identifier.is_empty())
+                .map(str::to_string)
+        })
+}
+
+/// Normalize `value` to `urn:isbn:` and an ISBN-13 if it is an ISBN: either
+/// labelled as one or a bare 13-digit number in the ISBN ranges.
+fn isbn(value: &str, scheme: Option<&str>) -> Option<String> {
+    let value = value.trim().to_ascii_lowercase();
+    // "15" is the ONIX code for ISBN-13.
+    let declared =
+        scheme.is_some_and(|scheme| scheme.eq_ignore_ascii_case("isbn") || scheme == "15");
+    let (declared, number) = match ["urn:isbn:", "isbn:", "isbn"]
+        .iter()
+        .find_map(|prefix| value.strip_prefix(prefix))
+    {
+        Some(number) => (true, number),
+        None => (declared, value.as_str()),
+    };
+    let digits: Vec<char> = number.chars().filter(|c| !matches!(c, '-' | ' ')).collect();
+    let is_digits = |digits: &[char]| digits.iter().all(char::is_ascii_digit);
+
+    let isbn13 = match digits.len() {
+        13 if is_digits(&digits) => {
+            let ranged =
+                digits.starts_with(&['9', '7', '8']) || digits.starts_with(&['9', '7', '9']);
+            (declared || ranged).then(|| digits.iter().collect::<String>())
+        }
+        10 if declared && is_digits(&digits[..9]) && matches!(digits[9], '0'..='9' | 'x') => {
+            // ISBN-10s become ISBN-13s with the 978 prefix and a new check digit.
+            let body = format!("978{}", digits[..9].iter().collect::<String>());
+            let sum: u32 = body
+                .chars()
+                .filter_map(|c| c.to_digit(10))
+                .enumerate()
+                .map(|(i, digit)| if i % 2 == 0 { digit } else { digit * 3 })
+                .sum();
+            Some(format!("{}{}", body, (10 - sum % 10) % 10))
+        }
+        _ => None,
+    }?;
+    Some(format!("urn:isbn:{}", isbn13))
+}
+
+impl TocExtractable for Epub {
+    fn extract_toc(&self) -> Result<Vec<TocEntry>, Box<dyn std::error::Error>> {
+        let mut doc = EpubDoc::new(&self.path).map_err(|e| e.to_string())?;
+        let nav_points = if doc.toc.is_empty() {
+            // EPUB 3 books may only ship a navigation document.
+            nav_document_points(&mut doc)
+        } else {
+            doc.toc.clone()
+        };
+        Ok(nav_points
+            .iter()
+            .map(|point| toc_entry(&doc, point))
+            .collect())
+    }
+}
+
+fn toc_entry<R: Read + Seek>(doc: &EpubDoc<R>, point: &NavPoint) -> TocEntry {
+    let content = point.content.to_string_lossy().replace('\\', "/");
+    let path = normalize(Path::new(content.split('#').next().unwrap_or_default()));
+    TocEntry {
+        title: point.label.trim().to_string(),
+        page_number: doc
+            .resource_uri_to_chapter(&path)
+            .map(|chapter| chapter as i32),
+        href: Some(content),
+        children: point
+            .children
+            .iter()
+            .map(|child| toc_entry(doc, child))
+            .collect(),
+    }
+}
+
+/// Resolve `.` and `..` in a path inside the archive.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// The entries of the `toc` nav of an EPUB 3 navigation document.
+fn nav_document_points<R: Read + Seek>(doc: &mut EpubDoc<R>) -> Vec<NavPoint> {
+    let Some(nav_id) = doc.get_nav_id() else {
+        return Vec::new();
+    };
+    let base = doc
+        .resources
+        .get(&nav_id)
+        .and_then(|resource| resource.path.parent().map(Path::to_path_buf))
+        .unwrap_or_default();
+    let Some((nav, _)) = doc.get_resource_str(&nav_id) else {
+        return Vec::new();
+    };
+    parse_nav(&nav, &base)
+}
+
+/// Parse the nested `<ol>` lists of the `<nav epub:type="toc">` element,
+/// resolving links against `base`.
+fn parse_nav(nav: &str, base: &Path) -> Vec<NavPoint> {
+    let mut toc_depth: Option<usize> = None;
+    let mut depth = 0;
+    // One list of entries per open `<ol>`.
+    let mut lists: Vec<Vec<NavPoint>> = Vec::new();
+    let mut entries = Vec::new();
+    let mut link: Option<(String, String)> = None;
+
+    for event in EventReader::from_str(nav) {
+        // Stop at malformed markup, keeping what was read so far.
+        let Ok(event) = event else { break };
+        match event {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                depth += 1;
+                let tag = name.local_name.as_str();
+                if tag == "nav" && toc_depth.is_none() {
+                    let is_toc = attributes.iter().any(|attribute| {
+                        attribute.name.local_name == "type"
+                            && attribute.value.split_whitespace().any(|kind| kind == "toc")
+                    });
+                    if is_toc && entries.is_empty() {
+                        toc_depth = Some(depth);
+                    }
+                }
+                if toc_depth.is_none() {
+                    continue;
+                }
+                match tag {
+                    "ol" => lists.push(Vec::new()),
+                    "a" | "span" if link.is_none() => {
+                        let href = attributes
+                            .iter()
+                            .find(|attribute| attribute.name.local_name == "href")
+                            .map(|attribute| attribute.value.clone())
+                            .unwrap_or_default();
+                        link = Some((href, String::new()));
+                    }
+                    _ => {}
+                }
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some((_, label)) = &mut link {
+                    label.push_str(&text);
+                }
+            }
+            XmlEvent::Whitespace(text) => {
+                if let Some((_, label)) = &mut link {
+                    label.push_str(&text);
+                }
+            }
+            XmlEvent::EndElement { name } => {
+                if let Some(toc) = toc_depth {
+                    match name.local_name.as_str() {
+                        "a" | "span" => {
+                            if let (Some((href, label)), Some(list)) =
+                                (link.take(), lists.last_mut())
+                            {
+                                list.push(NavPoint {
+                                    label: label.split_whitespace().collect::<Vec<_>>().join(" "),
+                                    content: base.join(href),
+                                    children: Vec::new(),
+                                    play_order: None,
+                                });
+                            }
+                        }
+                        "ol" => {
+                            let list = lists.pop().unwrap_or_default();
+                            match lists.last_mut().and_then(|parent| parent.last_mut()) {
+                                Some(parent) => parent.children = list,
+                                None => entries = list,
+                            }
+                        }
+                        _ => {}
+                    }
+                    if depth == toc {
+                        toc_depth = None;
+                    }
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    /// A one-chapter EPUB without a cover, with the given package metadata.
+    fn write_epub(dir: &Path, version: &str, metadata: &str) -> PathBuf {
+        use std::io::Write;
+
+        let path = dir.join("book.epub");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+        let options = zip::write::SimpleFileOptions::default();
+        let opf = format!(
+            r#"<?xml version="1.0"?><package xmlns="http://www.idpf.org/2007/opf" xmlns:opf="http://www.idpf.org/2007/opf" version="{}" unique-identifier="uid"><metadata xmlns:dc="http://purl.org/dc/elements/1.1/">{}</metadata><manifest><item id="one" href="one.xhtml" media-type="application/xhtml+xml"/></manifest><spine><itemref idref="one"/></spine></package>"#,
+            version, metadata
+        );
+        let files = [
+            ("mimetype", "application/epub+zip".to_string()),
+            (
+                "META-INF/container.xml",
+                r#"<?xml version="1.0"?><container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container"><rootfiles><rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/></rootfiles></container>"#.to_string(),
+            ),
+            ("OEBPS/content.opf", opf),
+            (
+                "OEBPS/one.xhtml",
+                "<html><body><p>Call me Ishmael.</p></body></html>".to_string(),
+            ),
+        ];
+        for (name, content) in files {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        path
+    }
+
+    #[test]
+    fn test_epub_3_metadata_is_extracted_and_a_missing_cover_is_generated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_epub(
+            dir.path(),
+            "3.0",
+            r##"<dc:title>Moby-Dick</dc:title>
+            <dc:identifier id="uid">urn:uuid:7d8a2c3e-0c4a-4b8e-9f5d-2a1b3c4d5e6f</dc:identifier>
+            <dc:identifier id="isbn">978-0-14-243724-7</dc:identifier>
+            <meta refines="#isbn" property="identifier-type" scheme="onix:codelist5">15</meta>
+            <dc:language>en</dc:language>
+            <dc:creator id="melville">Herman Melville</dc:creator>
+            <meta refines="#melville" property="role" scheme="marc:relators">aut</meta>
+            <dc:contributor id="kent">Rockwell Kent</dc:contributor>
+            <meta refines="#kent" property="role" scheme="marc:relators">ill</meta>
+            <dc:description>&lt;p&gt;The &lt;i&gt;Pequod&lt;/i&gt; hunts a whale.&lt;/p&gt;</dc:description>
+            <dc:subject>Whaling</dc:subject>
+            <dc:subject>Sea stories</dc:subject>
+            <dc:date>1851-10-18</dc:date>
+            <meta property="dcterms:modified">2020-01-01T00:00:00Z</meta>
+            <meta property="belongs-to-collection" id="set">Penguin Classics</meta>
+            <meta refines="#set" property="collection-type">set</meta>
+            <meta property="belongs-to-collection" id="series">The Sea Novels</meta>
+            <meta refines="#series" property="collection-type">series</meta>
+            <meta refines="#series" property="group-position">2</meta>"##,
+        );
+
+        let book = Epub::new(&path).extract().unwrap();
+
+        assert_eq!(book.cover_kind.as_deref(), Some("fallback"));
+        assert!(!book.cover.is_empty());
+        assert_eq!(book.identifier.as_deref(), Some("urn:isbn:9780142437247"));
+        assert_eq!(
+            book.metadata,
+            BookMetadata {
+                language: Some("en".to_string()),
+                identifiers: vec![
+                    "urn:uuid:7d8a2c3e-0c4a-4b8e-9f5d-2a1b3c4d5e6f".to_string(),
+                    "978-0-14-243724-7".to_string(),
+                ],
+                description: Some("The Pequod hunts a whale.".to_string()),
+                subjects: vec!["Whaling".to_string(), "Sea stories".to_string()],
+                series: Some("The Sea Novels".to_string()),
+                series_index: Some(2.0),
+                published: Some("1851-10-18".to_string()),
+                contributors: vec![
+                    Contributor {
+                        name: "Herman Melville".to_string(),
+                        role: Some("aut".to_string()),
+                    },
+                    Contributor {
+                        name: "Rockwell Kent".to_string(),
+                        role: Some("ill".to_string()),
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_epub_2_roles_dates_and_calibre_series_are_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_epub(
+            dir.path(),
+            "2.0",
+            r#"<dc:title>The Two Towers</dc:title>
+            <dc:identifier id="uid" opf:scheme="ISBN">0618002235</dc:identifier>
+            <dc:creator opf:role="aut" opf:file-as="Tolkien, J. R. R.">J. R. R. Tolkien</dc:creator>
+            <dc:contributor opf:role="edt">Christopher Tolkien</dc:contributor>
+            <dc:date opf:event="modification">2012-02-15</dc:date>
+            <dc:date opf:event="publication">1954-11-11</dc:date>
+            <meta name="calibre:series" content="The Lord of the Rings"/>
+            <meta name="calibre:series_index" content="2.0"/>"#,
+        );
+
+        let book = Epub::new(&path).extract().unwrap();
+
+        assert_eq!(book.identifier.as_deref(), Some("urn:isbn:9780618002238"));
+        assert_eq!(
+            book.metadata.series.as_deref(),
+            Some("The Lord of the Rings")
+        );
+        assert_eq!(book.metadata.series_index, Some(2.0));
+        assert_eq!(book.metadata.published.as_deref(), Some("1954-11-11"));
+        assert_eq!(
+            book.metadata.contributors,
+            vec![
+                Contributor {
+                    name: "J. R. R. Tolkien".to_string(),
+                    role: Some("aut".to_string()),
+                },
+                Contributor {
+                    name: "Christopher Tolkien".to_string(),
+                    role: Some("edt".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_isbns_are_normalized_to_isbn_13() {
+        let found: Vec<Option<String>> = [
+            ("urn:isbn:978-0-14-243724-7", None),
+            ("9780142437247", None),
+            ("0-14-243724-7", Some("ISBN")),
+            ("ISBN 0142437247", None),
+            ("0142437247", None),
+            ("urn:uuid:7d8a2c3e-0c4a-4b8e-9f5d-2a1b3c4d5e6f", None),
+        ]
+        .into_iter()
+        .map(|(value, scheme)| isbn(value, scheme))
+        .collect();
+
+        let moby_dick = Some("urn:isbn:9780142437247".to_string());
+        assert_eq!(
+            found,
+            vec![
+                moby_dick.clone(),
+                moby_dick.clone(),
+                moby_dick.clone(),
+                moby_dick,
+                // Ten bare digits could be anything.
+                None,
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nav_document_lists_become_nested_entries() {
+        let nav = r#"<?xml version="1.0" encoding="utf-8"?>
+            <html xmlns="http://www.w3.org/1999/xhtml" xmlns:epub="http://www.idpf.org/2007/ops">
+            <body>
+              <nav epub:type="landmarks"><ol><li><a href="cover.xhtml">Cover</a></li></ol></nav>
+              <nav epub:type="toc"><h1>Contents</h1>
+                <ol>
+                  <li><a href="text/one.xhtml">Part
+                      One</a>
+                    <ol><li><a href="text/one.xhtml#ch1">Chapter 1</a></li></ol>
+                  </li>
+                  <li><a href="../two.xhtml">Part Two</a></li>
+                </ol>
+              </nav>
+            </body></html>"#;
+
+        let points = parse_nav(nav, Path::new("OEBPS/nav"));
+
+        let labels: Vec<(&str, PathBuf, usize)> = points
+            .iter()
+            .map(|point| {
+                (
+                    point.label.as_str(),
+                    normalize(&point.content),
+                    point.children.len(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                ("Part One", PathBuf::from("OEBPS/nav/text/one.xhtml"), 1),
+                ("Part Two", PathBuf::from("OEBPS/two.xhtml"), 0),
+            ]
+        );
+        assert_eq!(points[0].children[0].label, "Chapter 1");
     }
 }
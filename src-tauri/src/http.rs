@@ -0,0 +1,237 @@
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
+
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+
+/// Default worker every backend call goes to.
+pub const DEFAULT_BASE_URL: &str = "https://rishi-worker.faridmato90.workers.dev";
+/// Overrides the configured base URL, e.g. to point a dev build at a staging
+/// worker or a local mock server.
+pub const BASE_URL_ENV: &str = "RISHI_WORKER_URL";
+
+const USER_AGENT: &str = concat!("Rishi/", env!("CARGO_PKG_VERSION"));
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Longest silence tolerated while reading a response; long streamed
+/// completions are fine as long as they keep producing data.
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How requests to the backend are made, as persisted in the app settings.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BackendSettings {
+    /// Worker base URL; `None` uses [`DEFAULT_BASE_URL`].
+    pub base_url: Option<String>,
+    /// Sent as a bearer token with every backend request.
+    pub auth_token: Option<String>,
+    /// How often a request that failed with a connection error, a timeout,
+    /// 429 or 5xx is retried.
+    pub max_retries: u32,
+    /// Delay before the first retry; doubled for each further attempt.
+    pub retry_backoff_ms: u64,
+}
+
+impl Default for BackendSettings {
+    fn default() -> Self {
+        Self {
+            base_url: None,
+            auth_token: None,
+            max_retries: 2,
+            retry_backoff_ms: 500,
+        }
+    }
+}
+
+/// An HTTP client bound to the backend: it resolves paths against the base
+/// URL, identifies the app, authenticates and retries transient failures.
+pub struct ApiClient {
+    client: reqwest::Client,
+    base_url: String,
+    auth_token: Option<String>,
+    max_retries: u32,
+    retry_backoff: Duration,
+}
+
+impl ApiClient {
+    /// Build a client from `settings`.
+    pub fn new(settings: &BackendSettings) -> reqwest::Result<Self> {
+        let base_url = settings
+            .base_url
+            .clone()
+            .unwrap_or_else(|| DEFAULT_BASE_URL.to_string());
+        Ok(Self {
+            client: shared_client()?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            auth_token: settings.auth_token.clone(),
+            max_retries: settings.max_retries,
+            retry_backoff: Duration::from_millis(settings.retry_backoff_ms),
+        })
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Absolute URL of a backend `path` such as `/api/audio/speech`.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path.trim_start_matches('/'))
+    }
+
+    pub fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self.client.request(method, self.url(path));
+        match &self.auth_token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    pub fn get(&self, path: &str) -> RequestBuilder {
+        self.request(Method::GET, path)
+    }
+
+    pub fn post(&self, path: &str) -> RequestBuilder {
+        self.request(Method::POST, path)
+    }
+
+    /// Send `request`, retrying with exponential backoff while it fails in a
+    /// way that may be temporary. Other responses, including 4xx, are
+    /// returned as is.
+    pub async fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        let mut backoff = self.retry_backoff;
+        for _ in 0..self.max_retries {
+            // Requests with streaming bodies can't be replayed.
+            let Some(attempt) = request.try_clone() else {
+                break;
+            };
+            match attempt.send().await {
+                Ok(response) if !is_transient_status(response.status()) => return Ok(response),
+                Err(e) if !(e.is_connect() || e.is_timeout()) => return Err(e),
+                _ => {}
+            }
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+        request.send().await
+    }
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// The one connection pool every outgoing request shares, with the app's
+/// user agent and timeouts.
+pub fn shared_client() -> reqwest::Result<reqwest::Client> {
+    static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+    if let Some(client) = CLIENT.get() {
+        return Ok(client.clone());
+    }
+    let client = reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(CONNECT_TIMEOUT)
+        .read_timeout(READ_TIMEOUT)
+        .build()?;
+    Ok(CLIENT.get_or_init(|| client).clone())
+}
+
+fn current_client() -> &'static RwLock<Option<Arc<ApiClient>>> {
+    static API_CLIENT: OnceLock<RwLock<Option<Arc<ApiClient>>>> = OnceLock::new();
+    API_CLIENT.get_or_init(|| RwLock::new(None))
+}
+
+/// The backend client, built from default settings until [`configure`] is called.
+pub fn api_client() -> reqwest::Result<Arc<ApiClient>> {
+    if let Some(client) = current_client()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+    {
+        return Ok(client.clone());
+    }
+    configure(&default_settings())
+}
+
+#[cfg(not(test))]
+fn default_settings() -> BackendSettings {
+    BackendSettings::default()
+}
+
+/// Tests talk to the in-process mock worker instead of the hosted one.
+#[cfg(test)]
+fn default_settings() -> BackendSettings {
+    BackendSettings {
+        base_url: Some(crate::test_worker::mock_worker_url().to_string()),
+        max_retries: 0,
+        ..Default::default()
+    }
+}
+
+/// `settings` with the base URL taken from [`BASE_URL_ENV`] when it is set.
+#[cfg(not(test))]
+fn with_env_overrides(settings: &BackendSettings) -> BackendSettings {
+    let base_url = std::env::var(BASE_URL_ENV)
+        .ok()
+        .filter(|url| !url.is_empty());
+    BackendSettings {
+        base_url: base_url.or_else(|| settings.base_url.clone()),
+        ..settings.clone()
+    }
+}
+
+/// Tests never leave the mock worker, whatever the environment says.
+#[cfg(test)]
+fn with_env_overrides(settings: &BackendSettings) -> BackendSettings {
+    settings.clone()
+}
+
+/// Make every backend call use `settings` from now on; the [`BASE_URL_ENV`]
+/// variable takes precedence over the configured base URL.
+pub fn configure(settings: &BackendSettings) -> reqwest::Result<Arc<ApiClient>> {
+    let client = Arc::new(ApiClient::new(&with_env_overrides(settings))?);
+    *current_client().write().unwrap_or_else(|e| e.into_inner()) = Some(client.clone());
+    Ok(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use expectest::prelude::*;
+    use reqwest::header;
+
+    use super::*;
+
+    #[test]
+    fn test_paths_resolve_against_the_configured_base_url() {
+        let client = ApiClient::new(&BackendSettings {
+            base_url: Some("http://localhost:8787/".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        expect!(client.base_url()).to(be_equal_to("http://localhost:8787"));
+        expect!(client.url("/api/audio/speech"))
+            .to(be_equal_to("http://localhost:8787/api/audio/speech"));
+        expect!(client.url("api/user/abc")).to(be_equal_to("http://localhost:8787/api/user/abc"));
+    }
+
+    #[test]
+    fn test_requests_carry_the_auth_token() {
+        let client = ApiClient::new(&BackendSettings {
+            auth_token: Some("secret".to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let request = client.get("/api/realtime/client_secrets").build().unwrap();
+
+        expect!(request.headers()[header::AUTHORIZATION].to_str().unwrap())
+            .to(be_equal_to("Bearer secret"));
+    }
+
+    #[test]
+    fn test_only_rate_limits_and_server_errors_are_retried() {
+        expect!(is_transient_status(StatusCode::TOO_MANY_REQUESTS)).to(be_true());
+        expect!(is_transient_status(StatusCode::BAD_GATEWAY)).to(be_true());
+        expect!(is_transient_status(StatusCode::NOT_FOUND)).to(be_false());
+        expect!(is_transient_status(StatusCode::OK)).to(be_false());
+    }
+}
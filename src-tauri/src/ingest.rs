@@ -0,0 +1,601 @@
+// Background ingestion: books are extracted, chunked and embedded one page
+// at a time by a single worker on the async runtime. Jobs live in the
+// `ingest_jobs` table, so pauses survive restarts and interrupted jobs pick
+// up after the last page whose vectors reached the disk.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use tauri::{Emitter, Manager};
+use tokio::sync::Notify;
+
+use crate::db::DB_POOL;
+use crate::models::IngestJobs;
+use crate::schema::ingest_jobs;
+use crate::{sql, vectordb};
+
+/// Emitted with an [`IngestJob`] whenever a job makes progress or changes state.
+pub const INGEST_PROGRESS_EVENT: &str = "ingest-progress";
+/// Vectors are flushed and progress recorded after this many pages; a
+/// crash loses at most this much work.
+const CHECKPOINT_PAGES: usize = 8;
+/// How long the worker waits before trying again after a failure such as a
+/// locked database; doubled for each failure in a row up to the maximum.
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IngestStatus {
+    Queued,
+    Running,
+    Paused,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+impl IngestStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IngestStatus::Queued => "queued",
+            IngestStatus::Running => "running",
+            IngestStatus::Paused => "paused",
+            IngestStatus::Done => "done",
+            IngestStatus::Failed => "failed",
+            IngestStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl std::str::FromStr for IngestStatus {
+    type Err = String;
+
+    fn from_str(status: &str) -> Result<Self, Self::Err> {
+        match status {
+            "queued" => Ok(IngestStatus::Queued),
+            "running" => Ok(IngestStatus::Running),
+            "paused" => Ok(IngestStatus::Paused),
+            "done" => Ok(IngestStatus::Done),
+            "failed" => Ok(IngestStatus::Failed),
+            "cancelled" => Ok(IngestStatus::Cancelled),
+            other => Err(format!("Unknown ingestion status: {}", other)),
+        }
+    }
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = ingest_jobs)]
+struct IngestJobInsertable<'a> {
+    book_id: i32,
+    status: &'a str,
+}
+
+/// Where a book's ingestion stands, as shown to the reader.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IngestJob {
+    pub book_id: i32,
+    pub status: IngestStatus,
+    /// Pages (or spine documents) with text; 0 until extraction has run.
+    pub total_pages: i32,
+    pub done_pages: i32,
+    pub error: Option<String>,
+}
+
+impl TryFrom<IngestJobs> for IngestJob {
+    type Error = String;
+
+    fn try_from(job: IngestJobs) -> Result<Self, Self::Error> {
+        Ok(Self {
+            book_id: job.book_id,
+            status: job.status.parse()?,
+            total_pages: job.total_pages,
+            done_pages: job.done_pages,
+            error: job.error,
+        })
+    }
+}
+
+/// Wakes the worker when a job becomes runnable.
+fn job_available() -> &'static Notify {
+    static JOB_AVAILABLE: OnceLock<Notify> = OnceLock::new();
+    JOB_AVAILABLE.get_or_init(Notify::new)
+}
+
+fn connection(
+) -> Result<diesel::r2d2::PooledConnection<diesel::r2d2::ConnectionManager<SqliteConnection>>, String>
+{
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    pool.get()
+        .map_err(|e| format!("Failed to get connection: {}", e))
+}
+
+fn find_job(conn: &mut SqliteConnection, book_id: i32) -> QueryResult<Option<IngestJobs>> {
+    ingest_jobs::table
+        .filter(ingest_jobs::book_id.eq(&book_id))
+        .select(IngestJobs::as_select())
+        .first::<IngestJobs>(conn)
+        .optional()
+}
+
+fn get_job(book_id: i32) -> Result<Option<IngestJobs>, String> {
+    find_job(&mut *connection()?, book_id).map_err(|e| format!("Failed to query ingestion: {}", e))
+}
+
+/// Move the book's job to `to` with `error`, if it is in one of the `from`
+/// states. Returns whether it was.
+fn set_status(
+    conn: &mut SqliteConnection,
+    book_id: i32,
+    from: &[IngestStatus],
+    to: IngestStatus,
+    error: Option<&str>,
+) -> Result<bool, String> {
+    let from: Vec<&str> = from.iter().map(IngestStatus::as_str).collect();
+    let updated = diesel::update(
+        ingest_jobs::table
+            .filter(ingest_jobs::book_id.eq(&book_id))
+            .filter(ingest_jobs::status.eq_any(&from)),
+    )
+    .set((
+        ingest_jobs::status.eq(to.as_str()),
+        ingest_jobs::error.eq(error),
+        ingest_jobs::updated_at.eq(diesel::dsl::now),
+    ))
+    .execute(conn)
+    .map_err(|e| format!("Failed to update ingestion: {}", e))?;
+    Ok(updated > 0)
+}
+
+/// Move the book's job from one of the `from` states to `to`.
+fn transition(book_id: i32, from: &[IngestStatus], to: IngestStatus) -> Result<IngestJob, String> {
+    let mut conn = connection()?;
+    let updated = set_status(&mut conn, book_id, from, to, None)?;
+
+    let job = find_job(&mut conn, book_id)
+        .map_err(|e| format!("Failed to query ingestion: {}", e))?
+        .ok_or_else(|| format!("Book {} has no ingestion job", book_id))?;
+    if !updated {
+        return Err(format!(
+            "Ingestion of book {} can't become {} while it is {}",
+            book_id,
+            to.as_str(),
+            job.status
+        ));
+    }
+    job.try_into()
+}
+
+/// Queue a book for ingestion. Failed, cancelled and paused jobs are
+/// queued again and continue where they stopped; queued, running and
+/// finished ones are left alone.
+#[tauri::command]
+pub fn enqueue_ingest(book_id: i32) -> Result<IngestJob, String> {
+    let mut conn = connection()?;
+    let job = conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            match find_job(conn, book_id)? {
+                None => {
+                    diesel::insert_into(ingest_jobs::table)
+                        .values(IngestJobInsertable {
+                            book_id,
+                            status: IngestStatus::Queued.as_str(),
+                        })
+                        .execute(conn)?;
+                }
+                Some(job)
+                    if job.status != IngestStatus::Queued.as_str()
+                        && job.status != IngestStatus::Running.as_str()
+                        && job.status != IngestStatus::Done.as_str() =>
+                {
+                    diesel::update(ingest_jobs::table.filter(ingest_jobs::id.eq(job.id)))
+                        .set((
+                            ingest_jobs::status.eq(IngestStatus::Queued.as_str()),
+                            ingest_jobs::error.eq(None::<String>),
+                            ingest_jobs::updated_at.eq(diesel::dsl::now),
+                        ))
+                        .execute(conn)?;
+                }
+                Some(_) => {}
+            }
+            find_job(conn, book_id)
+        })
+        .map_err(|e| format!("Failed to queue ingestion: {}", e))?
+        .ok_or_else(|| format!("Book {} has no ingestion job", book_id))?;
+    job_available().notify_one();
+    job.try_into()
+}
+
+/// Stop a book's ingestion after the page being embedded, keeping its place.
+#[tauri::command]
+pub fn pause_ingest(book_id: i32) -> Result<IngestJob, String> {
+    transition(
+        book_id,
+        &[IngestStatus::Queued, IngestStatus::Running],
+        IngestStatus::Paused,
+    )
+}
+
+#[tauri::command]
+pub fn resume_ingest(book_id: i32) -> Result<IngestJob, String> {
+    let job = transition(book_id, &[IngestStatus::Paused], IngestStatus::Queued)?;
+    job_available().notify_one();
+    Ok(job)
+}
+
+/// Stop a book's ingestion for good. Pages already indexed stay searchable.
+#[tauri::command]
+pub fn cancel_ingest(book_id: i32) -> Result<IngestJob, String> {
+    transition(
+        book_id,
+        &[
+            IngestStatus::Queued,
+            IngestStatus::Running,
+            IngestStatus::Paused,
+        ],
+        IngestStatus::Cancelled,
+    )
+}
+
+/// Every book's ingestion, e.g. to show progress when the library opens.
+#[tauri::command]
+pub fn get_ingest_jobs() -> Result<Vec<IngestJob>, String> {
+    let results = ingest_jobs::table
+        .order_by(ingest_jobs::id.asc())
+        .select(IngestJobs::as_select())
+        .load::<IngestJobs>(&mut connection()?)
+        .map_err(|e| format!("Failed to query ingestion: {}", e))?;
+
+    results.into_iter().map(IngestJob::try_from).collect()
+}
+
+/// Delete a book's ingestion job, as part of deleting the book.
+pub fn delete_job_for_book(conn: &mut SqliteConnection, book_id: i32) -> QueryResult<()> {
+    diesel::delete(ingest_jobs::table.filter(ingest_jobs::book_id.eq(&book_id))).execute(conn)?;
+    Ok(())
+}
+
+/// Make the pages up to `last_page` durable: flush the book's vectors, then
+/// record them as done.
+async fn checkpoint(
+    book_id: i32,
+    app_data_dir: &Path,
+    last_page: Option<i32>,
+    done_pages: i32,
+) -> Result<(), String> {
+    let app_data_dir = app_data_dir.to_path_buf();
+    let name = sql::book_vector_store_name(book_id);
+    tokio::task::spawn_blocking(move || vectordb::flush_vector_store(&app_data_dir, &name))
+        .await
+        .map_err(|e| format!("Vector flush task failed: {}", e))?
+        .map_err(|e| format!("Failed to flush vectors: {}", e))?;
+    diesel::update(ingest_jobs::table.filter(ingest_jobs::book_id.eq(&book_id)))
+        .set((
+            ingest_jobs::last_page.eq(last_page),
+            ingest_jobs::done_pages.eq(done_pages),
+            ingest_jobs::updated_at.eq(diesel::dsl::now),
+        ))
+        .execute(&mut connection()?)
+        .map_err(|e| format!("Failed to record ingestion progress: {}", e))?;
+    Ok(())
+}
+
+/// Move a running job to `status`, unless it was paused, cancelled or
+/// deleted meanwhile. Returns whether it was still running.
+fn finish(book_id: i32, status: IngestStatus, error: Option<&str>) -> Result<bool, String> {
+    set_status(
+        &mut *connection()?,
+        book_id,
+        &[IngestStatus::Running],
+        status,
+        error,
+    )
+}
+
+/// Index the pages of a job after its `last_page`, stopping early if the
+/// job is paused, cancelled or deleted meanwhile.
+async fn run_job(
+    job: IngestJobs,
+    app_data_dir: &PathBuf,
+    on_progress: &(dyn Fn(&IngestJob) + Send + Sync),
+) -> Result<(), String> {
+    let book_id = job.book_id;
+    // The job may have been paused or cancelled since it was picked.
+    let started = set_status(
+        &mut *connection()?,
+        book_id,
+        &[IngestStatus::Queued, IngestStatus::Running],
+        IngestStatus::Running,
+        None,
+    )?;
+    if !started {
+        return Ok(());
+    }
+    let pages = sql::chunks_by_page(sql::book_chunks(book_id).await?);
+    let total_pages = pages.len() as i32;
+    let mut last_page = job.last_page;
+    let remaining: Vec<_> = pages
+        .into_iter()
+        .filter(|(page_number, _)| last_page.is_none_or(|last| *page_number > last))
+        .collect();
+
+    let mut progress = IngestJob {
+        book_id,
+        status: IngestStatus::Running,
+        total_pages,
+        done_pages: total_pages - remaining.len() as i32,
+        error: None,
+    };
+    diesel::update(ingest_jobs::table.filter(ingest_jobs::book_id.eq(&book_id)))
+        .set(ingest_jobs::total_pages.eq(total_pages))
+        .execute(&mut connection()?)
+        .map_err(|e| format!("Failed to record ingestion progress: {}", e))?;
+    on_progress(&progress);
+
+    let mut unsaved_pages = 0;
+    for (page_number, page_data) in remaining {
+        let status = match get_job(book_id)? {
+            Some(job) => job.status.parse()?,
+            None => IngestStatus::Cancelled,
+        };
+        if status != IngestStatus::Running {
+            checkpoint(book_id, app_data_dir, last_page, progress.done_pages).await?;
+            progress.status = status;
+            on_progress(&progress);
+            return Ok(());
+        }
+
+        sql::index_page(page_number, book_id, page_data, app_data_dir).await?;
+        last_page = Some(page_number);
+        progress.done_pages += 1;
+        unsaved_pages += 1;
+        if unsaved_pages == CHECKPOINT_PAGES {
+            checkpoint(book_id, app_data_dir, last_page, progress.done_pages).await?;
+            unsaved_pages = 0;
+        }
+        on_progress(&progress);
+    }
+
+    checkpoint(book_id, app_data_dir, last_page, progress.done_pages).await?;
+    if finish(book_id, IngestStatus::Done, None)? {
+        progress.status = IngestStatus::Done;
+        on_progress(&progress);
+    }
+    Ok(())
+}
+
+/// Run the oldest queued (or interrupted) job until it finishes, fails or
+/// is paused. Returns whether there was a job to run.
+pub async fn run_next_job(
+    app_data_dir: &PathBuf,
+    on_progress: &(dyn Fn(&IngestJob) + Send + Sync),
+) -> Result<bool, String> {
+    let job = ingest_jobs::table
+        .filter(ingest_jobs::status.eq_any([
+            IngestStatus::Running.as_str(),
+            IngestStatus::Queued.as_str(),
+        ]))
+        .order_by(ingest_jobs::id.asc())
+        .select(IngestJobs::as_select())
+        .first::<IngestJobs>(&mut connection()?)
+        .optional()
+        .map_err(|e| format!("Failed to query ingestion: {}", e))?;
+    let Some(job) = job else {
+        return Ok(false);
+    };
+
+    let book_id = job.book_id;
+    if let Err(e) = run_job(job, app_data_dir, on_progress).await {
+        // A job cancelled or deleted while it ran stays that way.
+        if finish(book_id, IngestStatus::Failed, Some(&e))? {
+            if let Some(job) = get_job(book_id)? {
+                on_progress(&job.try_into()?);
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Run jobs as they are queued, forever.
+pub async fn run_worker(
+    app_data_dir: PathBuf,
+    on_progress: impl Fn(&IngestJob) + Send + Sync + 'static,
+) {
+    let mut retry_delay = RETRY_DELAY;
+    loop {
+        match run_next_job(&app_data_dir, &on_progress).await {
+            Ok(true) => retry_delay = RETRY_DELAY,
+            Ok(false) => {
+                retry_delay = RETRY_DELAY;
+                job_available().notified().await;
+            }
+            // Queued jobs may still be waiting, so look again rather than
+            // waiting for a new one.
+            Err(e) => {
+                eprintln!("Ingestion failed: {}", e);
+                tokio::time::sleep(retry_delay).await;
+                retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+            }
+        }
+    }
+}
+
+/// Start the worker, resuming whatever was queued or running when the app
+/// last closed. Progress is emitted to the webview as [`INGEST_PROGRESS_EVENT`].
+pub fn start(app: &tauri::AppHandle) -> anyhow::Result<()> {
+    let app_data_dir = app.path().app_data_dir()?;
+    let app = app.clone();
+    tauri::async_runtime::spawn(run_worker(app_data_dir, move |job| {
+        if let Err(e) = app.emit(INGEST_PROGRESS_EVENT, job) {
+            eprintln!("Failed to emit ingestion progress: {}", e);
+        }
+    }));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Mutex;
+
+    use expectest::prelude::*;
+
+    use super::*;
+    use crate::sql::{
+        get_all_page_data_by_book_id, save_book, save_page_data_many, BookInsertable,
+        ChunkDataInsertable,
+    };
+    use crate::test_helpers::init_test_database_setup;
+
+    /// A book backed by its own copy of the sample PDF.
+    fn sample_book(dir: &Path) -> Result<i32, String> {
+        let path = dir.join("sample.pdf");
+        let sample = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/fixtures/sample.pdf");
+        std::fs::copy(sample, &path).map_err(|e| e.to_string())?;
+        let book = save_book(
+            BookInsertable {
+                id: None,
+                kind: "pdf".to_string(),
+                cover: Vec::new(),
+                title: "Sample".to_string(),
+                author: String::new(),
+                publisher: String::new(),
+                filepath: path.to_string_lossy().to_string(),
+                location: "1".to_string(),
+                cover_kind: "fallback".to_string(),
+                version: 1,
+                // Copies of one file are one book; keep the tests' books apart.
+                content_hash: Some(path.to_string_lossy().to_string()),
+                identifier: None,
+            },
+            dir,
+        )?;
+        Ok(book.id)
+    }
+
+    #[test]
+    fn test_jobs_move_between_paused_queued_and_cancelled() -> Result<(), String> {
+        let setup = init_test_database_setup()?;
+        let book_id = sample_book(&setup.app_data_dir)?;
+
+        expect!(enqueue_ingest(book_id)?.status).to(be_equal_to(IngestStatus::Queued));
+        expect!(pause_ingest(book_id)?.status).to(be_equal_to(IngestStatus::Paused));
+        expect!(pause_ingest(book_id).is_err()).to(be_true());
+        expect!(resume_ingest(book_id)?.status).to(be_equal_to(IngestStatus::Queued));
+        expect!(cancel_ingest(book_id)?.status).to(be_equal_to(IngestStatus::Cancelled));
+        expect!(resume_ingest(book_id).is_err()).to(be_true());
+        // Queuing a cancelled book again picks it back up.
+        expect!(enqueue_ingest(book_id)?.status).to(be_equal_to(IngestStatus::Queued));
+        cancel_ingest(book_id)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_job_indexes_the_book_and_reports_progress() -> Result<(), String> {
+        let setup = init_test_database_setup()?;
+        let book_id = sample_book(&setup.app_data_dir)?;
+        enqueue_ingest(book_id)?;
+        let events = Mutex::new(Vec::new());
+
+        let job = get_job(book_id)?.unwrap();
+        run_job(job, &setup.app_data_dir, &|job: &IngestJob| {
+            events.lock().unwrap().push(job.clone())
+        })
+        .await?;
+
+        let events = events.into_inner().unwrap();
+        let last = events.last().unwrap();
+        expect!(last.status).to(be_equal_to(IngestStatus::Done));
+        expect!(last.total_pages).to(be_equal_to(1));
+        expect!(last.done_pages).to(be_equal_to(1));
+        expect!(get_all_page_data_by_book_id(book_id)?.is_empty()).to(be_false());
+        let job = get_job(book_id)?.unwrap();
+        expect!(job.status.as_str()).to(be_equal_to("done"));
+        expect!(job.last_page).to(be_some().value(1));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_job_replaces_chunks_saved_under_other_ids() -> Result<(), String> {
+        let setup = init_test_database_setup()?;
+        let book_id = sample_book(&setup.app_data_dir)?;
+        // As saved by the frontend, which numbers chunks its own way.
+        let foreign_ids = [8_800_001, 8_800_002];
+        save_page_data_many(
+            foreign_ids
+                .iter()
+                .map(|id| ChunkDataInsertable {
+                    id: Some(*id),
+                    page_number: 1,
+                    book_id,
+                    data: "Indexed by the frontend".to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+        )?;
+        enqueue_ingest(book_id)?;
+
+        let job = get_job(book_id)?.unwrap();
+        run_job(job, &setup.app_data_dir, &|_: &IngestJob| {}).await?;
+
+        let mut expected: Vec<i64> = sql::chunks_by_page(sql::book_chunks(book_id).await?)
+            .into_values()
+            .flatten()
+            .filter_map(|chunk| chunk.id)
+            .collect();
+        expected.sort();
+        let mut saved: Vec<i64> = get_all_page_data_by_book_id(book_id)?
+            .into_iter()
+            .map(|chunk| chunk.id)
+            .collect();
+        saved.sort();
+        expect!(expected.is_empty()).to(be_false());
+        expect!(saved).to(be_equal_to(expected));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_job_paused_before_it_starts_stays_paused() -> Result<(), String> {
+        let setup = init_test_database_setup()?;
+        let book_id = sample_book(&setup.app_data_dir)?;
+        enqueue_ingest(book_id)?;
+        let job = get_job(book_id)?.unwrap();
+        pause_ingest(book_id)?;
+
+        run_job(job, &setup.app_data_dir, &|_: &IngestJob| {}).await?;
+        expect!(get_all_page_data_by_book_id(book_id)?.is_empty()).to(be_true());
+        let job: IngestJob = get_job(book_id)?.unwrap().try_into()?;
+        expect!(job.status).to(be_equal_to(IngestStatus::Paused));
+
+        // Nor does a failure reported afterwards overwrite it.
+        expect!(finish(book_id, IngestStatus::Failed, Some("boom"))?).to(be_false());
+        let job: IngestJob = get_job(book_id)?.unwrap().try_into()?;
+        expect!(job.status).to(be_equal_to(IngestStatus::Paused));
+        expect!(job.error).to(be_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_interrupted_job_resumes_after_its_last_saved_page() -> Result<(), String> {
+        let setup = init_test_database_setup()?;
+        let book_id = sample_book(&setup.app_data_dir)?;
+        enqueue_ingest(book_id)?;
+        // As left by a run that saved page 1 and then lost the app.
+        transition(book_id, &[IngestStatus::Queued], IngestStatus::Running)?;
+        checkpoint(book_id, &setup.app_data_dir, Some(1), 1).await?;
+
+        let job = get_job(book_id)?.unwrap();
+        run_job(job, &setup.app_data_dir, &|_: &IngestJob| {}).await?;
+
+        // Nothing was left to embed.
+        expect!(get_all_page_data_by_book_id(book_id)?.is_empty()).to(be_true());
+        let job: IngestJob = get_job(book_id)?.unwrap().try_into()?;
+        expect!(job.status).to(be_equal_to(IngestStatus::Done));
+        expect!(job.done_pages).to(be_equal_to(1));
+        Ok(())
+    }
+}
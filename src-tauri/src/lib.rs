@@ -1,4 +1,6 @@
+pub mod chunker;
 mod commands;
+pub mod conversation;
 pub mod embed;
 mod epub;
 mod pdf;
@@ -7,11 +9,18 @@ pub mod vectordb;
 
 pub mod db;
 
+pub mod http;
+pub mod ingest;
+pub mod library;
+
 pub mod llm;
+pub mod llm_provider;
 pub mod models;
 pub mod schema;
+pub mod settings;
 pub mod speach;
 pub mod sql;
+pub mod toc;
 
 mod api;
 mod user;
@@ -25,6 +34,9 @@ pub mod test_fixtures;
 #[cfg(test)]
 pub mod test_helpers;
 
+#[cfg(test)]
+pub mod test_worker;
+
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -58,6 +70,20 @@ pub fn run() {
         .setup(|app| {
             //let _conn = db::init_database(app.handle())?;
             db::setup_database(app.handle())?;
+            if let Err(e) = settings::apply_saved_settings(app.handle()) {
+                eprintln!("Failed to load settings, using defaults: {}", e);
+            }
+            if let Err(e) = library::import_legacy_books(app.handle()) {
+                eprintln!("Failed to import books from store.json: {}", e);
+            }
+            tauri::async_runtime::spawn_blocking(|| {
+                if let Err(e) = sql::backfill_content_hashes() {
+                    eprintln!("Failed to hash existing books: {}", e);
+                }
+            });
+            embed::warm_up();
+            tauri::async_runtime::spawn(vectordb::flush_periodically());
+            ingest::start(app.handle())?;
             // You can store this conn somewhere global if needed
             Ok(())
         })
@@ -67,10 +93,31 @@ pub fn run() {
             commands::get_book_data,
             commands::get_pdf_data,
             commands::embed,
+            commands::get_embedding_model_status,
             commands::save_vectors,
             commands::search_vectors,
+            commands::flush_vectors,
             commands::process_job,
+            commands::index_book_text,
             commands::get_context_for_query,
+            commands::answer_question,
+            commands::stream_answer,
+            commands::cancel_answer_stream,
+            commands::ask_in_conversation,
+            conversation::start_conversation,
+            conversation::list_conversations,
+            conversation::get_conversation_messages,
+            conversation::delete_conversation,
+            toc::get_book_toc,
+            ingest::enqueue_ingest,
+            ingest::pause_ingest,
+            ingest::resume_ingest,
+            ingest::cancel_ingest,
+            ingest::get_ingest_jobs,
+            library::import_folder,
+            library::get_missing_books,
+            settings::get_settings,
+            settings::set_settings,
             commands::get_state,
             commands::get_user,
             commands::signout,
@@ -80,15 +127,24 @@ pub fn run() {
             // SQL commands
             sql::save_page_data_many,
             sql::get_all_page_data_by_book_id,
-            sql::save_book,
+            commands::save_book,
             sql::get_book,
             sql::get_books,
-            sql::delete_book,
+            commands::delete_book,
+            commands::book_needs_reindex,
+            commands::reindex_book,
             sql::update_book_cover,
             sql::has_saved_epub_data,
             sql::update_book_location,
             sql::get_text_from_vector_id,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            if let tauri::RunEvent::Exit = event {
+                if let Err(e) = vectordb::flush_vectors() {
+                    eprintln!("Failed to flush vector indexes on exit: {}", e);
+                }
+            }
+        });
 }
@@ -0,0 +1,494 @@
+// Importing books from folders on disk, either once or by watching them.
+// Books imported this way stay where they are instead of being copied into
+// the app data directory.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use serde::Serialize;
+use serde_json::json;
+use tauri::ipc::Channel;
+use tauri::{Emitter, Manager};
+use tauri_plugin_store::StoreExt;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::epub::Epub;
+use crate::pdf::Pdf;
+use crate::shared::books::Extractable;
+use crate::shared::types::{BookData, BookKind};
+use crate::sql::{self, Book, BookInsertable};
+
+/// Emitted with a [`LibraryEvent`] when a watched folder changes.
+pub const LIBRARY_EVENT: &str = "library-changed";
+/// How long a watched path must stay unchanged before it is looked at, so
+/// files still being copied in are not read half-written.
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+/// `store.json` key books were listed under before the library moved to
+/// the `books` table.
+const LEGACY_BOOKS_KEY: &str = "books";
+
+/// Progress of an import, sent to the frontend over a channel or as a
+/// [`LIBRARY_EVENT`].
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "event", content = "data", rename_all = "camelCase")]
+pub enum LibraryEvent {
+    /// How many books were found, sent before any is imported.
+    Found(usize),
+    Imported(Box<Book>),
+    Failed {
+        path: PathBuf,
+        error: String,
+    },
+    /// Ids of the books whose file has disappeared.
+    Missing(Vec<i32>),
+    Done(ImportSummary),
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportSummary {
+    pub imported: usize,
+    pub failed: usize,
+}
+
+fn book_kind(path: &Path) -> Option<BookKind> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "epub" => Some(BookKind::Epub),
+        "pdf" => Some(BookKind::Pdf),
+        _ => None,
+    }
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with('.'))
+}
+
+/// Every EPUB and PDF under `dir`, skipping hidden files and folders.
+pub fn find_books(dir: &Path) -> Vec<PathBuf> {
+    let mut books = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if is_hidden(&path) {
+                continue;
+            }
+            // The entry's own type: linked folders are not followed, so
+            // they can't lead back up the tree.
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => dirs.push(path),
+                Ok(_) if book_kind(&path).is_some() => books.push(path),
+                _ => {}
+            }
+        }
+    }
+    books.sort();
+    books
+}
+
+fn extract(path: &Path) -> Result<BookData, String> {
+    let extractable: Box<dyn Extractable> = match book_kind(path) {
+        Some(BookKind::Epub) => Box::new(Epub::new(path)),
+        Some(BookKind::Pdf) => Box::new(Pdf::new(path)),
+        None => return Err(format!("Not an EPUB or PDF: {}", path.display())),
+    };
+    extractable.extract().map_err(|e| e.to_string())
+}
+
+/// Import `paths`, extracting them in parallel and reporting each book to
+/// `on_event` as it is saved. Files imported before resolve to their
+/// existing book, see [`sql::save_book`].
+pub async fn import_books(
+    paths: Vec<PathBuf>,
+    app_data_dir: &Path,
+    on_event: impl Fn(LibraryEvent),
+) -> ImportSummary {
+    let threads = std::thread::available_parallelism().map_or(4, usize::from);
+    let permits = Arc::new(Semaphore::new(threads));
+    let mut extractions = JoinSet::new();
+    for path in paths {
+        let permits = permits.clone();
+        extractions.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            let extracted = tokio::task::spawn_blocking({
+                let path = path.clone();
+                move || extract(&path)
+            })
+            .await
+            .map_err(|e| format!("Import task failed: {}", e))
+            .and_then(|extracted| extracted);
+            (path, extracted)
+        });
+    }
+
+    let mut summary = ImportSummary::default();
+    while let Some(joined) = extractions.join_next().await {
+        let Ok((path, extracted)) = joined else {
+            summary.failed += 1;
+            continue;
+        };
+        // Books are saved one at a time so SQLite never sees two writers.
+        let saved = match extracted {
+            Ok(book) => {
+                let app_data_dir = app_data_dir.to_path_buf();
+                tokio::task::spawn_blocking(move || sql::save_book_data(book, &app_data_dir))
+                    .await
+                    .map_err(|e| format!("Import task failed: {}", e))
+                    .and_then(|saved| saved)
+            }
+            Err(error) => Err(error),
+        };
+        match saved {
+            Ok(book) => {
+                summary.imported += 1;
+                on_event(LibraryEvent::Imported(Box::new(book)));
+            }
+            Err(error) => {
+                summary.failed += 1;
+                on_event(LibraryEvent::Failed { path, error });
+            }
+        }
+    }
+    summary
+}
+
+/// Import every EPUB and PDF under `dir`, streaming progress to `on_event`.
+#[tauri::command]
+pub async fn import_folder(
+    app: tauri::AppHandle,
+    dir: PathBuf,
+    on_event: Channel<LibraryEvent>,
+) -> Result<ImportSummary, String> {
+    let app_data_dir = app
+        .path()
+        .app_data_dir()
+        .map_err(|e| format!("Failed to get app data directory: {:?}", e))?;
+    let paths = tokio::task::spawn_blocking(move || find_books(&dir))
+        .await
+        .map_err(|e| format!("Folder scan failed: {}", e))?;
+    let _ = on_event.send(LibraryEvent::Found(paths.len()));
+    let summary = import_books(paths, &app_data_dir, |event| {
+        let _ = on_event.send(event);
+    })
+    .await;
+    let _ = on_event.send(LibraryEvent::Done(summary.clone()));
+    Ok(summary)
+}
+
+/// Ids of the books whose file is no longer where it was imported from.
+#[tauri::command]
+pub fn get_missing_books() -> Result<Vec<i32>, String> {
+    Ok(sql::get_books()?
+        .into_iter()
+        .filter(|book| !Path::new(&book.filepath).exists())
+        .map(|book| book.id)
+        .collect())
+}
+
+/// A book as listed in `store.json`. Its id hashed the file's path rather
+/// than its content, so the hash is left for [`sql::save_book`] to compute.
+fn legacy_insertable(book: BookData) -> BookInsertable {
+    BookInsertable {
+        content_hash: None,
+        ..BookInsertable::from(book)
+    }
+}
+
+/// Import the books listed in `store.json` that are also in the `books`
+/// table. Books were never removed from the list when they were deleted,
+/// so the ones missing from the table are left out. Returns the books that
+/// failed to save.
+fn import_legacy_entries(
+    books: Vec<BookData>,
+    app_data_dir: &Path,
+) -> Result<Vec<BookData>, String> {
+    let kept: HashSet<String> = sql::get_books()?
+        .into_iter()
+        .map(|book| book.filepath)
+        .collect();
+
+    let mut failed = Vec::new();
+    for book in books {
+        if !kept.contains(&book.filepath) {
+            continue;
+        }
+        if let Err(e) = sql::save_book(legacy_insertable(book.clone()), app_data_dir) {
+            eprintln!("Failed to import {} from store.json: {}", book.filepath, e);
+            failed.push(book);
+        }
+    }
+    Ok(failed)
+}
+
+/// Move the books listed in `store.json` into the `books` table; called at
+/// startup. Books that fail to save stay listed and are retried next time.
+pub fn import_legacy_books(app: &tauri::AppHandle) -> anyhow::Result<()> {
+    let store = app.store("store.json")?;
+    let Some(value) = store.get(LEGACY_BOOKS_KEY) else {
+        return Ok(());
+    };
+    let books: Vec<BookData> = serde_json::from_value(value)?;
+    let app_data_dir = app.path().app_data_dir()?;
+
+    let failed = import_legacy_entries(books, &app_data_dir).map_err(anyhow::Error::msg)?;
+    if failed.is_empty() {
+        store.delete(LEGACY_BOOKS_KEY);
+    } else {
+        store.set(LEGACY_BOOKS_KEY, json!(failed));
+    }
+    store.save()?;
+    Ok(())
+}
+
+fn watchers() -> &'static Mutex<HashMap<PathBuf, Debouncer<RecommendedWatcher>>> {
+    static WATCHERS: OnceLock<Mutex<HashMap<PathBuf, Debouncer<RecommendedWatcher>>>> =
+        OnceLock::new();
+    WATCHERS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Watch `folders` instead of the folders watched so far. Books added to
+/// them are imported and books whose file disappears are reported, as
+/// [`LIBRARY_EVENT`]s. Returns the folders that could not be watched.
+pub fn watch_folders(app: &tauri::AppHandle, folders: &[PathBuf]) -> Vec<PathBuf> {
+    let mut watchers = watchers().lock().unwrap_or_else(|e| e.into_inner());
+    watchers.retain(|folder, _| folders.contains(folder));
+    let mut failed = Vec::new();
+    for folder in folders {
+        if watchers.contains_key(folder) {
+            continue;
+        }
+        match watch_folder(app, folder) {
+            Ok(debouncer) => {
+                watchers.insert(folder.clone(), debouncer);
+            }
+            Err(e) => {
+                eprintln!("Failed to watch {}: {}", folder.display(), e);
+                failed.push(folder.clone());
+            }
+        }
+    }
+    failed
+}
+
+fn watch_folder(
+    app: &tauri::AppHandle,
+    folder: &Path,
+) -> notify_debouncer_mini::notify::Result<Debouncer<RecommendedWatcher>> {
+    let app = app.clone();
+    let mut debouncer = new_debouncer(
+        SETTLE_TIME,
+        move |result: DebounceEventResult| match result {
+            Ok(events) => {
+                let paths = events.into_iter().map(|event| event.path).collect();
+                tauri::async_runtime::spawn(apply_changes(app.clone(), paths));
+            }
+            Err(e) => eprintln!("Library watcher failed: {}", e),
+        },
+    )?;
+    debouncer
+        .watcher()
+        .watch(folder, RecursiveMode::Recursive)?;
+    Ok(debouncer)
+}
+
+/// Bring the library in line with the changed `paths` of a watched folder.
+async fn apply_changes(app: tauri::AppHandle, paths: Vec<PathBuf>) {
+    let emit = |event: LibraryEvent| {
+        if let Err(e) = app.emit(LIBRARY_EVENT, event) {
+            eprintln!("Failed to emit library change: {}", e);
+        }
+    };
+
+    let mut added = Vec::new();
+    let mut removed = false;
+    for path in paths {
+        if is_hidden(&path) {
+            continue;
+        }
+        if path.is_dir() {
+            // A whole folder moved in.
+            added.extend(find_books(&path));
+        } else if path.is_file() {
+            if book_kind(&path).is_some() {
+                added.push(path);
+            }
+        } else {
+            removed = true;
+        }
+    }
+
+    // Imports come first: a book moved within the folder is relinked
+    // rather than reported missing.
+    if !added.is_empty() {
+        match app.path().app_data_dir() {
+            Ok(app_data_dir) => {
+                import_books(added, &app_data_dir, &emit).await;
+            }
+            Err(e) => eprintln!("Failed to get app data directory: {:?}", e),
+        }
+    }
+    if removed {
+        match get_missing_books() {
+            Ok(missing) if !missing.is_empty() => emit(LibraryEvent::Missing(missing)),
+            Ok(_) => {}
+            Err(e) => eprintln!("Failed to check for missing books: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::Mutex;
+
+    use expectest::prelude::*;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::test_helpers::init_test_database_setup;
+
+    #[test]
+    fn test_find_books_walks_subfolders_and_skips_hidden_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for folder in ["Fiction/Melville", ".trash", "Papers"] {
+            fs::create_dir_all(root.join(folder)).unwrap();
+        }
+        for file in [
+            "Fiction/Melville/Moby Dick.EPUB",
+            "Fiction/notes.txt",
+            ".trash/Deleted.epub",
+            "Papers/attention.pdf",
+            "Papers/.draft.pdf",
+        ] {
+            fs::write(root.join(file), b"").unwrap();
+        }
+
+        let found: Vec<PathBuf> = find_books(root)
+            .into_iter()
+            .map(|path| path.strip_prefix(root).unwrap().to_path_buf())
+            .collect();
+
+        assert_eq!(
+            found,
+            vec![
+                PathBuf::from("Fiction/Melville/Moby Dick.EPUB"),
+                PathBuf::from("Papers/attention.pdf"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_legacy_store_books_keep_their_details_but_not_their_ids() {
+        // As written by `store_book_data`, before books had identifiers.
+        let book: BookData = serde_json::from_value(serde_json::json!({
+            "id": "5d41402abc4b2a76b9719d911017c592",
+            "kind": "epub",
+            "cover": [1, 2, 3],
+            "title": "Moby Dick",
+            "author": null,
+            "publisher": "Harper",
+            "filepath": "/books/moby-dick.epub",
+            "location": "epubcfi(/6/14!/4/2)",
+            "coverKind": null,
+            "version": 2,
+        }))
+        .unwrap();
+
+        let insertable = legacy_insertable(book);
+
+        expect!(insertable.content_hash).to(be_none());
+        expect!(insertable.identifier).to(be_none());
+        expect!(insertable.title.as_str()).to(be_equal_to("Moby Dick"));
+        expect!(insertable.author.as_str()).to(be_equal_to(""));
+        expect!(insertable.filepath.as_str()).to(be_equal_to("/books/moby-dick.epub"));
+        expect!(insertable.location.as_str()).to(be_equal_to("epubcfi(/6/14!/4/2)"));
+        expect!(insertable.version).to(be_equal_to(2));
+    }
+
+    #[test]
+    fn test_legacy_books_deleted_from_the_library_are_not_imported_again() -> Result<(), String> {
+        let setup = init_test_database_setup()?;
+        let legacy_book = |name: &str| -> Result<BookData, String> {
+            let path = setup.app_data_dir.join(name);
+            fs::write(&path, format!("legacy {}", name)).map_err(|e| e.to_string())?;
+            serde_json::from_value(serde_json::json!({
+                "id": name,
+                "kind": "epub",
+                "cover": [],
+                "title": name,
+                "author": null,
+                "publisher": null,
+                "filepath": path.to_string_lossy(),
+                "location": "",
+                "coverKind": null,
+                "version": 1,
+            }))
+            .map_err(|e| e.to_string())
+        };
+        let kept = legacy_book("kept.epub")?;
+        let deleted = legacy_book("deleted.epub")?;
+        let saved = sql::save_book(legacy_insertable(kept.clone()), &setup.app_data_dir)?;
+
+        let failed =
+            import_legacy_entries(vec![kept.clone(), deleted.clone()], &setup.app_data_dir)?;
+
+        expect!(failed.is_empty()).to(be_true());
+        let books = sql::get_books()?;
+        let kept_books: Vec<i32> = books
+            .iter()
+            .filter(|book| book.filepath == kept.filepath)
+            .map(|book| book.id)
+            .collect();
+        expect!(kept_books).to(be_equal_to(vec![saved.id]));
+        expect!(books.iter().any(|book| book.filepath == deleted.filepath)).to(be_false());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_import_books_saves_good_files_and_reports_bad_ones() -> Result<(), String> {
+        let setup = init_test_database_setup()?;
+        let sample = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/fixtures/sample.pdf");
+        let pdf = setup.app_data_dir.join("sample.pdf");
+        fs::copy(sample, &pdf).map_err(|e| e.to_string())?;
+        let broken = setup.app_data_dir.join("broken.epub");
+        fs::write(&broken, b"not a zip").map_err(|e| e.to_string())?;
+        let events = Mutex::new(Vec::new());
+
+        let summary = import_books(
+            vec![pdf.clone(), broken.clone()],
+            &setup.app_data_dir,
+            |event| events.lock().unwrap().push(event),
+        )
+        .await;
+
+        expect!(summary).to(be_equal_to(ImportSummary {
+            imported: 1,
+            failed: 1,
+        }));
+        let events = events.into_inner().unwrap();
+        let imported = events.iter().find_map(|event| match event {
+            LibraryEvent::Imported(book) => Some(book),
+            _ => None,
+        });
+        let imported = imported.ok_or("sample.pdf was not imported")?;
+        expect!(imported.kind.as_str()).to(be_equal_to("pdf"));
+        expect!(imported.filepath.clone()).to(be_equal_to(pdf.to_string_lossy().to_string()));
+        let failed = events
+            .iter()
+            .any(|event| matches!(event, LibraryEvent::Failed { path, .. } if *path == broken));
+        expect!(failed).to(be_true());
+        Ok(())
+    }
+}
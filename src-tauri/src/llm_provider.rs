@@ -0,0 +1,335 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::http;
+use crate::llm::{read_completion_stream, ChatTurn, Role};
+
+const WORKER_COMPLETIONS_PATH: &str = "/api/text/completions";
+
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A backend that turns a prompt into a completion.
+pub trait LlmProvider: Send + Sync {
+    /// Complete `messages`, returning the whole response.
+    fn complete<'a>(&'a self, messages: &'a [ChatTurn]) -> BoxFuture<'a, anyhow::Result<String>>;
+
+    /// Complete `messages`, handing each piece of text to `on_token` as it
+    /// is produced. Returns the whole response.
+    fn stream<'a>(
+        &'a self,
+        messages: &'a [ChatTurn],
+        on_token: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxFuture<'a, anyhow::Result<String>>;
+}
+
+/// Which provider to use, as persisted in the app settings.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum LlmSettings {
+    /// The hosted Rishi worker.
+    #[default]
+    Worker,
+    /// Any server exposing the OpenAI chat completions API, such as a local
+    /// llama.cpp or Ollama server.
+    #[serde(rename_all = "camelCase")]
+    OpenAiCompatible {
+        /// Base URL up to and including the API version, e.g. `http://localhost:11434/v1`.
+        base_url: String,
+        model: String,
+        api_key: Option<String>,
+    },
+    /// Canned responses, for tests and offline development.
+    Mock,
+}
+
+impl LlmSettings {
+    pub fn provider(&self) -> Arc<dyn LlmProvider> {
+        match self {
+            LlmSettings::Worker => Arc::new(WorkerProvider),
+            LlmSettings::OpenAiCompatible {
+                base_url,
+                model,
+                api_key,
+            } => Arc::new(OpenAiCompatibleProvider::new(
+                base_url,
+                model,
+                api_key.clone(),
+            )),
+            LlmSettings::Mock => Arc::new(MockProvider),
+        }
+    }
+}
+
+fn current_provider() -> &'static RwLock<Arc<dyn LlmProvider>> {
+    static PROVIDER: OnceLock<RwLock<Arc<dyn LlmProvider>>> = OnceLock::new();
+    // Tests never talk to the hosted worker.
+    let settings = if cfg!(test) {
+        LlmSettings::Mock
+    } else {
+        LlmSettings::default()
+    };
+    PROVIDER.get_or_init(|| RwLock::new(settings.provider()))
+}
+
+/// The provider completions currently go to.
+pub fn provider() -> Arc<dyn LlmProvider> {
+    current_provider()
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+}
+
+/// Send completions to the provider described by `settings` from now on.
+pub fn use_provider(settings: &LlmSettings) {
+    *current_provider()
+        .write()
+        .unwrap_or_else(|e| e.into_inner()) = settings.provider();
+}
+
+/// The hosted worker, reached through the configured [`http::api_client`].
+/// It takes a single `input` string: a bare question, or a JSON-encoded
+/// `{"input": [messages]}` for a full conversation.
+pub struct WorkerProvider;
+
+impl WorkerProvider {
+    fn body(messages: &[ChatTurn], stream: bool) -> Value {
+        let input = match messages {
+            [ChatTurn {
+                role: Role::User,
+                content,
+            }] => content.clone(),
+            _ => json!({ "input": messages }).to_string(),
+        };
+        if stream {
+            json!({ "input": input, "stream": true })
+        } else {
+            json!({ "input": input })
+        }
+    }
+}
+
+impl LlmProvider for WorkerProvider {
+    fn complete<'a>(&'a self, messages: &'a [ChatTurn]) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let client = http::api_client()?;
+            let response = client
+                .send(
+                    client
+                        .post(WORKER_COMPLETIONS_PATH)
+                        .json(&Self::body(messages, false)),
+                )
+                .await?
+                .text()
+                .await?;
+            Ok(response)
+        })
+    }
+
+    fn stream<'a>(
+        &'a self,
+        messages: &'a [ChatTurn],
+        on_token: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let client = http::api_client()?;
+            let response = client
+                .send(
+                    client
+                        .post(WORKER_COMPLETIONS_PATH)
+                        .json(&Self::body(messages, true)),
+                )
+                .await?;
+            read_completion_stream(response, on_token).await
+        })
+    }
+}
+
+/// A server implementing the OpenAI `/chat/completions` API.
+pub struct OpenAiCompatibleProvider {
+    url: String,
+    model: String,
+    api_key: Option<String>,
+}
+
+impl OpenAiCompatibleProvider {
+    pub fn new(base_url: &str, model: &str, api_key: Option<String>) -> Self {
+        Self {
+            url: format!("{}/chat/completions", base_url.trim_end_matches('/')),
+            model: model.to_string(),
+            api_key,
+        }
+    }
+
+    fn request(
+        &self,
+        messages: &[ChatTurn],
+        stream: bool,
+    ) -> anyhow::Result<reqwest::RequestBuilder> {
+        let request = http::shared_client()?.post(&self.url).json(&json!({
+            "model": self.model,
+            "messages": messages,
+            "stream": stream,
+        }));
+        Ok(match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        })
+    }
+}
+
+impl LlmProvider for OpenAiCompatibleProvider {
+    fn complete<'a>(&'a self, messages: &'a [ChatTurn]) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let response: Value = self
+                .request(messages, false)?
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            response["choices"][0]["message"]["content"]
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| anyhow::anyhow!("Completion response has no message content"))
+        })
+    }
+
+    fn stream<'a>(
+        &'a self,
+        messages: &'a [ChatTurn],
+        on_token: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let response = self
+                .request(messages, true)?
+                .send()
+                .await?
+                .error_for_status()?;
+            read_completion_stream(response, on_token).await
+        })
+    }
+}
+
+/// Answers deterministically without any network access. When the prompt
+/// contains passages the reply cites the first one.
+pub struct MockProvider;
+
+impl MockProvider {
+    fn reply(messages: &[ChatTurn]) -> String {
+        let question = messages
+            .iter()
+            .rev()
+            .find(|message| message.role == Role::User)
+            .map(|message| message.content.as_str())
+            .unwrap_or_default();
+        if question.contains("<passages>") {
+            "This is a mock answer based on the first passage [1].".to_string()
+        } else {
+            format!("This is a mock answer to: {}", question)
+        }
+    }
+}
+
+impl LlmProvider for MockProvider {
+    fn complete<'a>(&'a self, messages: &'a [ChatTurn]) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move { Ok(Self::reply(messages)) })
+    }
+
+    fn stream<'a>(
+        &'a self,
+        messages: &'a [ChatTurn],
+        on_token: &'a mut (dyn FnMut(&str) + Send),
+    ) -> BoxFuture<'a, anyhow::Result<String>> {
+        Box::pin(async move {
+            let reply = Self::reply(messages);
+            for token in reply.split_inclusive(' ') {
+                on_token(token);
+            }
+            Ok(reply)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expectest::prelude::*;
+    use serde_json::json;
+
+    use super::*;
+
+    fn user(content: &str) -> ChatTurn {
+        ChatTurn {
+            role: Role::User,
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_worker_body_sends_lone_questions_as_plain_input() {
+        let system = ChatTurn {
+            role: Role::System,
+            content: "Be brief.".to_string(),
+        };
+
+        expect!(WorkerProvider::body(&[user("Hi")], false))
+            .to(be_equal_to(json!({ "input": "Hi" })));
+        let body = WorkerProvider::body(&[system, user("Hi")], true);
+        let input: Value = serde_json::from_str(body["input"].as_str().unwrap()).unwrap();
+        expect!(input).to(be_equal_to(json!({ "input": [
+            { "role": "system", "content": "Be brief." },
+            { "role": "user", "content": "Hi" },
+        ]})));
+        expect!(body["stream"].as_bool()).to(be_equal_to(Some(true)));
+    }
+
+    #[test]
+    fn test_settings_round_trip_with_a_kind_tag() {
+        let settings = LlmSettings::OpenAiCompatible {
+            base_url: "http://localhost:11434/v1".to_string(),
+            model: "llama3".to_string(),
+            api_key: None,
+        };
+        let value = serde_json::to_value(&settings).unwrap();
+
+        expect!(value["kind"].as_str()).to(be_equal_to(Some("openAiCompatible")));
+        expect!(value["baseUrl"].as_str()).to(be_equal_to(Some("http://localhost:11434/v1")));
+        expect!(serde_json::from_value::<LlmSettings>(value).unwrap()).to(be_equal_to(settings));
+    }
+
+    #[tokio::test]
+    async fn test_worker_completes_and_streams_through_the_api_client() {
+        let messages = [user("Who is Ahab?")];
+        let mut streamed = String::new();
+        let mut on_token = |token: &str| streamed.push_str(token);
+
+        let completed = WorkerProvider.complete(&messages).await.unwrap();
+        let returned = WorkerProvider
+            .stream(&messages, &mut on_token)
+            .await
+            .unwrap();
+
+        expect!(completed.as_str()).to(be_equal_to(
+            "This is a mock completion for a 12-character prompt.",
+        ));
+        expect!(returned).to(be_equal_to(completed.clone()));
+        expect!(streamed).to(be_equal_to(completed));
+    }
+
+    #[tokio::test]
+    async fn test_mock_streams_the_same_text_it_completes() {
+        let messages = [user("Who is Ahab?")];
+        let mut streamed = String::new();
+        let mut on_token = |token: &str| streamed.push_str(token);
+
+        let completed = MockProvider.complete(&messages).await.unwrap();
+        let returned = MockProvider.stream(&messages, &mut on_token).await.unwrap();
+
+        expect!(completed.as_str()).to(be_equal_to("This is a mock answer to: Who is Ahab?"));
+        expect!(returned).to(be_equal_to(completed.clone()));
+        expect!(streamed).to(be_equal_to(completed));
+    }
+}
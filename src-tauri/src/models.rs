@@ -14,6 +14,16 @@ pub struct Books {
     pub location: String,
     pub cover_kind: String,
     pub version: i32,
+    pub content_hash: Option<String>,
+    pub identifier: Option<String>,
+    pub language: Option<String>,
+    pub identifiers: String,
+    pub description: Option<String>,
+    pub subjects: String,
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
+    pub published: Option<String>,
+    pub contributors: String,
 }
 
 #[derive(Queryable, Selectable)]
@@ -26,4 +36,58 @@ pub struct ChunkData {
     #[diesel(column_name = bookId)]
     pub book_id: i32,
     pub data: String,
+    #[diesel(column_name = chapterId)]
+    pub chapter_id: Option<i32>,
+    pub href: Option<String>,
+    #[diesel(column_name = startOffset)]
+    pub start_offset: Option<i32>,
+    #[diesel(column_name = endOffset)]
+    pub end_offset: Option<i32>,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = crate::schema::conversations)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Conversations {
+    pub id: i32,
+    pub book_id: i32,
+    pub title: String,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = crate::schema::ingest_jobs)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct IngestJobs {
+    pub id: i32,
+    pub book_id: i32,
+    pub status: String,
+    pub total_pages: i32,
+    pub done_pages: i32,
+    pub last_page: Option<i32>,
+    pub error: Option<String>,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = crate::schema::messages)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Messages {
+    pub id: i32,
+    pub conversation_id: i32,
+    pub role: String,
+    pub content: String,
+    pub citations: String,
+}
+
+#[derive(Queryable, Selectable)]
+#[diesel(table_name = crate::schema::toc_entries)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct TocEntries {
+    pub id: i32,
+    pub book_id: i32,
+    pub position: i32,
+    pub parent_position: Option<i32>,
+    pub level: i32,
+    pub title: String,
+    pub page_number: Option<i32>,
+    pub href: Option<String>,
 }
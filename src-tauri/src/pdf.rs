@@ -1,19 +1,28 @@
 use std::{
-    fmt::Display,
+    collections::{HashMap, HashSet},
+    io::{Cursor, Read},
     path::{Path, PathBuf},
+    rc::Rc,
 };
 
+use crate::chunker::Section;
 use crate::shared::{
-    books::Extractable,
-    types::{BookData, BookKind},
+    books::{content_hash, create_placeholder_cover, Cover, Extractable, TocExtractable},
+    types::{BookData, BookKind, TocEntry},
 };
+use flate2::read::ZlibDecoder;
+use image::{DynamicImage, ImageFormat, RgbImage};
+use pdf::content::{Matrix, Op, TextDrawAdjusted};
+use pdf::enc::{LZWFlateParams, StreamFilter};
+use pdf::encoding::BaseEncoding;
+use pdf::error::PdfError;
 use pdf::file::FileOptions;
-
-pub enum Cover {
-    #[allow(dead_code)]
-    Normal(Vec<u8>),
-    Fallback(Vec<u8>),
-}
+use pdf::font::{Font, ToUnicodeMap, Widths};
+use pdf::object::{
+    Action, ColorSpace, Dest, ImageXObject, MaybeNamedDest, Object, OutlineItem, Page, PlainRef,
+    Ref, Resolve, Resources, XObject,
+};
+use pdf::primitive::{Name, Primitive};
 
 pub struct Pdf {
     path: PathBuf,
@@ -24,6 +33,27 @@ impl Pdf {
             path: path.to_path_buf(),
         }
     }
+
+    /// The text of every page, in reading order.
+    pub fn page_texts(&self) -> Result<Vec<PageText>, Box<dyn std::error::Error>> {
+        let file = FileOptions::cached().open(&self.path)?;
+        let resolver = file.resolver();
+
+        let mut pages = Vec::new();
+        for (index, page) in file.pages().enumerate() {
+            let page = page?;
+            let mut extractor = TextExtractor::new(&resolver);
+            if let Some(contents) = &page.contents {
+                let operations = contents.operations(&resolver)?;
+                extractor.run(&operations, page.resources()?, 0)?;
+            }
+            pages.push(PageText {
+                page_number: index as i32 + 1,
+                text: layout(&extractor.spans),
+            });
+        }
+        Ok(pages)
+    }
 }
 impl Extractable for Pdf {
     fn extract(&self) -> Result<BookData, Box<dyn std::error::Error>> {
@@ -55,10 +85,16 @@ impl Extractable for Pdf {
             .as_ref()
             .and_then(|dict| dict.creator.as_ref())
             .and_then(|s| s.to_string().ok());
-        let cover = create_placeholder_cover()?;
+        let first_page_image = file
+            .get_page(0)
+            .ok()
+            .and_then(|page| page_cover(&page, &file.resolver()));
+        let cover = match first_page_image {
+            Some(image) => Cover::Normal(image),
+            None => create_placeholder_cover()?,
+        };
         let pdf_path = path.to_str().unwrap_or_default().to_string();
-        let digest = md5::compute(path.to_string_lossy().to_string());
-        let id = format!("{:x}", digest);
+        let id = content_hash(path)?;
         let kind = BookKind::Pdf.to_string();
         let current_location = "1".to_string();
         let cover_kind = Some(cover.to_string());
@@ -90,88 +126,1216 @@ impl Extractable for Pdf {
     }
 }
 
-impl Display for Cover {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Cover::Normal(_) => write!(f, "normal"),
-            Cover::Fallback(_) => write!(f, "fallback"),
+impl TocExtractable for Pdf {
+    fn extract_toc(&self) -> Result<Vec<TocEntry>, Box<dyn std::error::Error>> {
+        let file = FileOptions::cached().open(&self.path)?;
+        let resolver = file.resolver();
+        let Some(outlines) = &file.get_root().outlines else {
+            return Ok(Vec::new());
+        };
+
+        let mut page_numbers = HashMap::new();
+        for (index, page) in file.pages().enumerate() {
+            page_numbers.insert(page?.get_ref().get_inner(), index as i32 + 1);
+        }
+        let mut outline = Outline {
+            resolver: &resolver,
+            page_numbers,
+            named_dests: named_dests(file.get_root(), &resolver),
+            seen: HashSet::new(),
+        };
+        Ok(outline.entries(outlines.first, 0))
+    }
+}
+
+/// Outlines nested deeper than this are flattened into their parent.
+const MAX_OUTLINE_DEPTH: usize = 16;
+
+/// Walks the outline tree, resolving each item's destination to a page.
+struct Outline<'a, R: Resolve> {
+    resolver: &'a R,
+    page_numbers: HashMap<PlainRef, i32>,
+    named_dests: HashMap<Vec<u8>, PlainRef>,
+    /// Items already visited; malformed files can link siblings in a cycle.
+    seen: HashSet<PlainRef>,
+}
+
+impl<R: Resolve> Outline<'_, R> {
+    fn entries(&mut self, first: Option<Ref<OutlineItem>>, depth: usize) -> Vec<TocEntry> {
+        let mut entries = Vec::new();
+        let mut next = first;
+        while let Some(item_ref) = next {
+            if !self.seen.insert(item_ref.get_inner()) {
+                break;
+            }
+            let Ok(item) = self.resolver.get(item_ref) else {
+                break;
+            };
+            next = item.next;
+            let children = if depth < MAX_OUTLINE_DEPTH {
+                self.entries(item.first, depth + 1)
+            } else {
+                Vec::new()
+            };
+            let title = item
+                .title
+                .as_ref()
+                .map(|title| collapse_spaces(&title.to_string_lossy()))
+                .unwrap_or_default();
+            if title.is_empty() && children.is_empty() {
+                continue;
+            }
+            entries.push(TocEntry {
+                title,
+                page_number: self.page_number(&item),
+                href: None,
+                children,
+            });
+        }
+        entries
+    }
+
+    fn page_number(&self, item: &OutlineItem) -> Option<i32> {
+        let page = match (&item.dest, &item.action) {
+            (Some(dest), _) => self.dest_page(dest.clone()),
+            (None, Some(Action::Goto(MaybeNamedDest::Direct(dest)))) => {
+                dest.page.map(|page| page.get_inner())
+            }
+            (None, Some(Action::Goto(MaybeNamedDest::Named(name)))) => {
+                self.named_dests.get(name.as_bytes()).copied()
+            }
+            _ => None,
+        }?;
+        self.page_numbers.get(&page).copied()
+    }
+
+    /// The page an outline item's `/Dest` points at: an explicit destination
+    /// array or the name of one.
+    fn dest_page(&self, dest: Primitive) -> Option<PlainRef> {
+        match dest {
+            Primitive::Name(name) => self.named_dests.get(name.as_bytes()).copied(),
+            Primitive::String(name) => self.named_dests.get(name.as_bytes()).copied(),
+            dest => Dest::from_primitive(dest, self.resolver)
+                .ok()?
+                .page
+                .map(|page| page.get_inner()),
+        }
+    }
+}
+
+/// Named destinations from both the `/Dests` name tree and the older
+/// `/Dests` dictionary of the catalog, mapped to the page they point at.
+fn named_dests(
+    catalog: &pdf::object::Catalog,
+    resolve: &impl Resolve,
+) -> HashMap<Vec<u8>, PlainRef> {
+    let mut dests = HashMap::new();
+    if let Some(tree) = catalog
+        .names
+        .as_ref()
+        .and_then(|names| names.dests.as_ref())
+    {
+        let _ = tree.walk(resolve, &mut |name, dest| {
+            if let Some(page) = dest.as_ref().and_then(|dest| dest.page) {
+                dests.insert(name.as_bytes().to_vec(), page.get_inner());
+            }
+        });
+    }
+    if let Some(dictionary) = &catalog.dests {
+        for (name, dest) in dictionary.iter() {
+            if let Some(page) = Dest::from_primitive(dest.clone(), resolve)
+                .ok()
+                .and_then(|dest| dest.page)
+            {
+                dests.insert(name.as_bytes().to_vec(), page.get_inner());
+            }
         }
     }
+    dests
 }
 
-fn create_placeholder_cover() -> Result<Cover, Box<dyn std::error::Error>> {
-    use image::{ImageFormat, Rgba, RgbaImage};
-    use std::io::Cursor;
+/// Images smaller than this on either side are logos or ornaments, not covers.
+const MIN_COVER_SIDE: u32 = 100;
 
-    // Create a book-like placeholder cover (400x600 - typical book aspect ratio)
-    let width = 400u32;
-    let height = 600u32;
+/// The largest usable image drawn on `page`, as JPEG or PNG bytes.
+fn page_cover(page: &Page, resolve: &impl Resolve) -> Option<Vec<u8>> {
+    let resources = page.resources().ok()?;
+    let mut xobjects: Vec<_> = resources
+        .xobjects
+        .values()
+        .filter_map(|reference| resolve.get(*reference).ok())
+        .collect();
+    let area = |xobject: &XObject| match xobject {
+        XObject::Image(image) => image.width as u64 * image.height as u64,
+        _ => 0,
+    };
+    xobjects.sort_by_key(|xobject| std::cmp::Reverse(area(xobject)));
 
-    // Create a more book-like background with a subtle pattern
-    let mut img = RgbaImage::new(width, height);
+    xobjects.iter().find_map(|xobject| match &**xobject {
+        XObject::Image(image)
+            if !image.image_mask
+                && image.width >= MIN_COVER_SIDE
+                && image.height >= MIN_COVER_SIDE =>
+        {
+            encode_cover(image, resolve)
+        }
+        _ => None,
+    })
+}
 
-    for (x, y, pixel) in img.enumerate_pixels_mut() {
-        // Create a subtle book-like texture
-        let gradient_y = (y as f32 / height as f32).min(1.0);
-        let _gradient_x = (x as f32 / width as f32).min(1.0);
+/// JPEG images are kept as they are, unless they are CMYK, which browsers
+/// render poorly. Flate-compressed and uncompressed samples become PNG.
+/// Returns `None` for encodings and colour spaces we can't turn into RGB.
+fn encode_cover(image: &ImageXObject, resolve: &impl Resolve) -> Option<Vec<u8>> {
+    let (data, filter) = image.raw_image_data(resolve).ok()?;
+    let color_space = image.color_space.as_ref()?;
+    match filter {
+        Some(StreamFilter::DCTDecode(_)) => {
+            let decoded = image::load_from_memory_with_format(&data, ImageFormat::Jpeg).ok()?;
+            if components(color_space) == Some(4) {
+                encode(
+                    &DynamicImage::ImageRgb8(decoded.to_rgb8()),
+                    ImageFormat::Jpeg,
+                )
+            } else {
+                Some(data.to_vec())
+            }
+        }
+        Some(StreamFilter::FlateDecode(params)) => {
+            let samples = inflate(&data, params)?;
+            let rgb = to_rgb(image, color_space, &samples)?;
+            encode(&DynamicImage::ImageRgb8(rgb), ImageFormat::Png)
+        }
+        None => {
+            let rgb = to_rgb(image, color_space, &data)?;
+            encode(&DynamicImage::ImageRgb8(rgb), ImageFormat::Png)
+        }
+        _ => None,
+    }
+}
 
-        // Base colors for a book cover look
-        let base_r = 45u8;
-        let base_g = 55u8;
-        let base_b = 70u8;
+fn encode(image: &DynamicImage, format: ImageFormat) -> Option<Vec<u8>> {
+    let mut buffer = Vec::new();
+    image.write_to(&mut Cursor::new(&mut buffer), format).ok()?;
+    Some(buffer)
+}
+
+/// Decompress Flate data and undo a PNG predictor, if one was used.
+fn inflate(data: &[u8], params: &LZWFlateParams) -> Option<Vec<u8>> {
+    let mut inflated = Vec::new();
+    ZlibDecoder::new(data).read_to_end(&mut inflated).ok()?;
+    match params.predictor {
+        1 => Some(inflated),
+        10..=15 => {
+            let bits_per_pixel = (params.n_components * params.bits_per_component).max(1) as usize;
+            let bytes_per_pixel = bits_per_pixel.div_ceil(8);
+            let row_len = (params.columns.max(1) as usize * bits_per_pixel).div_ceil(8);
+            unpredict_png(&inflated, row_len, bytes_per_pixel)
+        }
+        // TIFF predictors are rare in images and not supported.
+        _ => None,
+    }
+}
+
+/// Reverse PNG row filters: each row is a filter type byte followed by
+/// `row_len` filtered bytes.
+fn unpredict_png(data: &[u8], row_len: usize, bytes_per_pixel: usize) -> Option<Vec<u8>> {
+    let mut output: Vec<u8> = Vec::with_capacity(data.len());
+    let mut previous = vec![0u8; row_len];
+    for row in data.chunks(row_len + 1) {
+        let (&filter, row) = row.split_first()?;
+        let mut current = row.to_vec();
+        current.resize(row_len, 0);
+        for i in 0..row_len {
+            let left = if i >= bytes_per_pixel {
+                current[i - bytes_per_pixel]
+            } else {
+                0
+            };
+            let up = previous[i];
+            let up_left = if i >= bytes_per_pixel {
+                previous[i - bytes_per_pixel]
+            } else {
+                0
+            };
+            let prediction = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return None,
+            };
+            current[i] = current[i].wrapping_add(prediction);
+        }
+        output.extend_from_slice(&current);
+        previous = current;
+    }
+    Some(output)
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - up_left as i16;
+    let distance = |value: u8| (estimate - value as i16).abs();
+    if distance(left) <= distance(up) && distance(left) <= distance(up_left) {
+        left
+    } else if distance(up) <= distance(up_left) {
+        up
+    } else {
+        up_left
+    }
+}
+
+/// Number of colour components of a gray, RGB or CMYK colour space.
+fn components(color_space: &ColorSpace) -> Option<usize> {
+    match color_space {
+        ColorSpace::DeviceGray | ColorSpace::CalGray(_) => Some(1),
+        ColorSpace::DeviceRGB | ColorSpace::CalRGB(_) => Some(3),
+        ColorSpace::DeviceCMYK | ColorSpace::CalCMYK(_) => Some(4),
+        ColorSpace::Icc(profile) => match profile.info.components {
+            n @ (1 | 3 | 4) => Some(n as usize),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn rgb_from(color: &[u8]) -> [u8; 3] {
+    match *color {
+        [gray] => [gray, gray, gray],
+        [r, g, b] => [r, g, b],
+        [c, m, y, k] => {
+            let channel = |value: u8| ((255 - value as u16) * (255 - k as u16) / 255) as u8;
+            [channel(c), channel(m), channel(y)]
+        }
+        _ => [0, 0, 0],
+    }
+}
+
+/// Convert 8-bit samples to RGB, looking colours of indexed images up in
+/// their palette.
+fn to_rgb(image: &ImageXObject, color_space: &ColorSpace, samples: &[u8]) -> Option<RgbImage> {
+    if image.bits_per_component.unwrap_or(8) != 8 {
+        return None;
+    }
+    let pixel_count = image.width as usize * image.height as usize;
+    let pixels: Vec<u8> = match color_space {
+        ColorSpace::Indexed(base, _, palette) => {
+            let n = components(base)?;
+            samples
+                .get(..pixel_count)?
+                .iter()
+                .flat_map(|&index| {
+                    let start = index as usize * n;
+                    rgb_from(palette.get(start..start + n).unwrap_or(&[]))
+                })
+                .collect()
+        }
+        _ => {
+            let n = components(color_space)?;
+            samples
+                .get(..pixel_count * n)?
+                .chunks(n)
+                .flat_map(rgb_from)
+                .collect()
+        }
+    };
+    RgbImage::from_raw(image.width, image.height, pixels)
+}
+
+/// Text of one page, numbered from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageText {
+    pub page_number: i32,
+    pub text: String,
+}
+
+impl From<PageText> for Section {
+    fn from(page: PageText) -> Self {
+        Section {
+            page_number: page.page_number,
+            text: page.text,
+            href: None,
+        }
+    }
+}
+
+/// Form XObjects nested deeper than this are ignored.
+const MAX_FORM_DEPTH: usize = 8;
+
+/// MacRomanEncoding for codes 0x80 to 0xFF; the lower half is ASCII.
+const MAC_ROMAN_HIGH: &str = concat!(
+    "ÄÅÇÉÑÖÜáàâäãåçéè",
+    "êëíìîïñóòôöõúùûü",
+    "†°¢£§•¶ß®©™´¨≠ÆØ",
+    "∞±≤≥¥µ∂∑∏π∫ªºΩæø",
+    "¿¡¬√ƒ≈∆«»…\u{a0}ÀÃÕŒœ",
+    "–—“”‘’÷◊ÿŸ⁄€‹›ﬁﬂ",
+    "‡·‚„‰ÂÊÁËÈÍÎÏÌÓÔ",
+    "\u{f8ff}ÒÚÛÙıˆ˜¯˘˙˚¸˝˛ˇ",
+);
+
+/// WinAnsiEncoding for codes 0x80 to 0x9F, with `\0` for unused codes;
+/// 0xA0 to 0xFF match Latin-1.
+const WIN_ANSI_C1: &str = "€\0‚ƒ„…†‡ˆ‰Š‹Œ\0Ž\0\0‘’“”•–—˜™š›œ\0žŸ";
+
+/// Glyph names used in font `Differences` arrays that aren't a single
+/// character or a `uniXXXX` name.
+const GLYPH_NAMES: &[(&str, &str)] = &[
+    ("space", " "),
+    ("exclam", "!"),
+    ("quotedbl", "\""),
+    ("numbersign", "#"),
+    ("dollar", "$"),
+    ("percent", "%"),
+    ("ampersand", "&"),
+    ("quotesingle", "'"),
+    ("parenleft", "("),
+    ("parenright", ")"),
+    ("asterisk", "*"),
+    ("plus", "+"),
+    ("comma", ","),
+    ("hyphen", "-"),
+    ("period", "."),
+    ("slash", "/"),
+    ("zero", "0"),
+    ("one", "1"),
+    ("two", "2"),
+    ("three", "3"),
+    ("four", "4"),
+    ("five", "5"),
+    ("six", "6"),
+    ("seven", "7"),
+    ("eight", "8"),
+    ("nine", "9"),
+    ("colon", ":"),
+    ("semicolon", ";"),
+    ("less", "<"),
+    ("equal", "="),
+    ("greater", ">"),
+    ("question", "?"),
+    ("at", "@"),
+    ("bracketleft", "["),
+    ("backslash", "\\"),
+    ("bracketright", "]"),
+    ("asciicircum", "^"),
+    ("underscore", "_"),
+    ("grave", "`"),
+    ("braceleft", "{"),
+    ("bar", "|"),
+    ("braceright", "}"),
+    ("asciitilde", "~"),
+    ("quoteleft", "‘"),
+    ("quoteright", "’"),
+    ("quotedblleft", "“"),
+    ("quotedblright", "”"),
+    ("quotesinglbase", "‚"),
+    ("quotedblbase", "„"),
+    ("guilsinglleft", "‹"),
+    ("guilsinglright", "›"),
+    ("guillemotleft", "«"),
+    ("guillemotright", "»"),
+    ("endash", "–"),
+    ("emdash", "—"),
+    ("bullet", "•"),
+    ("ellipsis", "…"),
+    ("dagger", "†"),
+    ("daggerdbl", "‡"),
+    ("section", "§"),
+    ("paragraph", "¶"),
+    ("copyright", "©"),
+    ("registered", "®"),
+    ("trademark", "™"),
+    ("degree", "°"),
+    ("periodcentered", "·"),
+    ("nbspace", " "),
+    ("exclamdown", "¡"),
+    ("questiondown", "¿"),
+    ("cent", "¢"),
+    ("sterling", "£"),
+    ("yen", "¥"),
+    ("Euro", "€"),
+    ("multiply", "×"),
+    ("divide", "÷"),
+    ("minus", "−"),
+    ("fi", "fi"),
+    ("fl", "fl"),
+    ("ff", "ff"),
+    ("ffi", "ffi"),
+    ("ffl", "ffl"),
+    ("AE", "Æ"),
+    ("ae", "æ"),
+    ("OE", "Œ"),
+    ("oe", "œ"),
+    ("Oslash", "Ø"),
+    ("oslash", "ø"),
+    ("Eth", "Ð"),
+    ("eth", "ð"),
+    ("Thorn", "Þ"),
+    ("thorn", "þ"),
+    ("germandbls", "ß"),
+    ("dotlessi", "ı"),
+];
+
+/// Accents that glyph names append to a base letter, e.g. `eacute`.
+const GLYPH_ACCENTS: &[(&str, char)] = &[
+    ("acute", '\u{301}'),
+    ("grave", '\u{300}'),
+    ("circumflex", '\u{302}'),
+    ("dieresis", '\u{308}'),
+    ("tilde", '\u{303}'),
+    ("cedilla", '\u{327}'),
+    ("ring", '\u{30a}'),
+    ("caron", '\u{30c}'),
+];
+
+/// Precomposed Latin letters for a base letter and combining accent.
+const COMPOSED: &[(char, char, char)] = &[
+    ('A', '\u{300}', 'À'),
+    ('A', '\u{301}', 'Á'),
+    ('A', '\u{302}', 'Â'),
+    ('A', '\u{303}', 'Ã'),
+    ('A', '\u{308}', 'Ä'),
+    ('A', '\u{30a}', 'Å'),
+    ('C', '\u{327}', 'Ç'),
+    ('C', '\u{30c}', 'Č'),
+    ('E', '\u{300}', 'È'),
+    ('E', '\u{301}', 'É'),
+    ('E', '\u{302}', 'Ê'),
+    ('E', '\u{308}', 'Ë'),
+    ('I', '\u{300}', 'Ì'),
+    ('I', '\u{301}', 'Í'),
+    ('I', '\u{302}', 'Î'),
+    ('I', '\u{308}', 'Ï'),
+    ('N', '\u{303}', 'Ñ'),
+    ('O', '\u{300}', 'Ò'),
+    ('O', '\u{301}', 'Ó'),
+    ('O', '\u{302}', 'Ô'),
+    ('O', '\u{303}', 'Õ'),
+    ('O', '\u{308}', 'Ö'),
+    ('S', '\u{30c}', 'Š'),
+    ('U', '\u{300}', 'Ù'),
+    ('U', '\u{301}', 'Ú'),
+    ('U', '\u{302}', 'Û'),
+    ('U', '\u{308}', 'Ü'),
+    ('Y', '\u{301}', 'Ý'),
+    ('Y', '\u{308}', 'Ÿ'),
+    ('Z', '\u{30c}', 'Ž'),
+    ('a', '\u{300}', 'à'),
+    ('a', '\u{301}', 'á'),
+    ('a', '\u{302}', 'â'),
+    ('a', '\u{303}', 'ã'),
+    ('a', '\u{308}', 'ä'),
+    ('a', '\u{30a}', 'å'),
+    ('c', '\u{327}', 'ç'),
+    ('c', '\u{30c}', 'č'),
+    ('e', '\u{300}', 'è'),
+    ('e', '\u{301}', 'é'),
+    ('e', '\u{302}', 'ê'),
+    ('e', '\u{308}', 'ë'),
+    ('i', '\u{300}', 'ì'),
+    ('i', '\u{301}', 'í'),
+    ('i', '\u{302}', 'î'),
+    ('i', '\u{308}', 'ï'),
+    ('n', '\u{303}', 'ñ'),
+    ('o', '\u{300}', 'ò'),
+    ('o', '\u{301}', 'ó'),
+    ('o', '\u{302}', 'ô'),
+    ('o', '\u{303}', 'õ'),
+    ('o', '\u{308}', 'ö'),
+    ('s', '\u{30c}', 'š'),
+    ('u', '\u{300}', 'ù'),
+    ('u', '\u{301}', 'ú'),
+    ('u', '\u{302}', 'û'),
+    ('u', '\u{308}', 'ü'),
+    ('y', '\u{301}', 'ý'),
+    ('y', '\u{308}', 'ÿ'),
+    ('z', '\u{30c}', 'ž'),
+];
+
+/// Unicode for a glyph name from the Adobe Glyph List conventions.
+fn glyph_name_to_unicode(name: &str) -> Option<String> {
+    if let Some((_, text)) = GLYPH_NAMES.iter().find(|(glyph, _)| *glyph == name) {
+        return Some(text.to_string());
+    }
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(c.to_string());
+    }
+    // Variants such as `a.sc` or `f_i` decode like their base glyphs.
+    if let Some((base, _)) = name.split_once('.') {
+        return glyph_name_to_unicode(base);
+    }
+    if name.contains('_') {
+        return name.split('_').map(glyph_name_to_unicode).collect();
+    }
+    if let Some(hex) = name.strip_prefix("uni") {
+        return hex
+            .as_bytes()
+            .chunks(4)
+            .map(|code| {
+                let code = std::str::from_utf8(code).ok()?;
+                char::from_u32(u32::from_str_radix(code, 16).ok()?)
+            })
+            .collect();
+    }
+    if let Some(hex) = name.strip_prefix('u') {
+        if (4..=6).contains(&hex.len()) {
+            let code = u32::from_str_radix(hex, 16).ok()?;
+            return char::from_u32(code).map(String::from);
+        }
+    }
+    GLYPH_ACCENTS.iter().find_map(|(accent, mark)| {
+        let base = name.strip_suffix(accent)?.chars().next()?;
+        COMPOSED
+            .iter()
+            .find(|(letter, accent, _)| *letter == base && accent == mark)
+            .map(|(_, _, composed)| composed.to_string())
+    })
+}
+
+fn base_encoding_char(base: &BaseEncoding, code: u8) -> Option<char> {
+    match (base, code) {
+        (_, 0x20..=0x7E) => Some(match (base, code) {
+            (BaseEncoding::StandardEncoding, 0x27) => '’',
+            (BaseEncoding::StandardEncoding, 0x60) => '‘',
+            _ => code as char,
+        }),
+        (BaseEncoding::MacRomanEncoding, 0x80..) => {
+            MAC_ROMAN_HIGH.chars().nth(code as usize - 0x80)
+        }
+        (BaseEncoding::WinAnsiEncoding, 0x80..=0x9F) => WIN_ANSI_C1
+            .chars()
+            .nth(code as usize - 0x80)
+            .filter(|&c| c != '\0'),
+        (BaseEncoding::WinAnsiEncoding, 0xA0..) => Some(code as char),
+        (_, 0xAE) => Some('ﬁ'),
+        (_, 0xAF) => Some('ﬂ'),
+        (_, 0xB1) => Some('–'),
+        (_, 0xD0) => Some('—'),
+        _ => None,
+    }
+}
+
+/// Maps the codes of a font's strings to Unicode and glyph widths.
+struct FontDecoder {
+    /// Composite fonts use two-byte codes.
+    two_byte: bool,
+    to_unicode: Option<ToUnicodeMap>,
+    /// Unicode for each single-byte code, from the font's encoding.
+    simple: Vec<Option<String>>,
+    widths: Option<Widths>,
+}
+
+impl FontDecoder {
+    fn new(font: &Font, resolve: &impl Resolve) -> Self {
+        let encoding = font.encoding();
+        let base = encoding
+            .map(|encoding| encoding.base.clone())
+            .unwrap_or(BaseEncoding::StandardEncoding);
+        let simple = (0..=255u8)
+            .map(|code| {
+                let difference = encoding
+                    .and_then(|encoding| encoding.differences.get(&(code as u32)))
+                    .and_then(|name| glyph_name_to_unicode(name));
+                difference.or_else(|| base_encoding_char(&base, code).map(String::from))
+            })
+            .collect();
+        Self {
+            two_byte: font.is_cid(),
+            // A broken ToUnicode map is ignored in favour of the encoding.
+            to_unicode: font.to_unicode(resolve).and_then(Result::ok),
+            simple,
+            widths: font.widths(resolve).ok().flatten(),
+        }
+    }
 
-        // Add some texture and variation
-        let noise = ((x + y) % 7) as u8 * 3;
-        let edge_darken = if x < 20 || x > width - 20 || y < 20 || y > height - 20 {
-            20
+    fn codes(&self, bytes: &[u8]) -> Vec<u32> {
+        if self.two_byte {
+            bytes
+                .chunks(2)
+                .map(|pair| pair.iter().fold(0, |code, &byte| (code << 8) | byte as u32))
+                .collect()
         } else {
-            0
-        };
+            bytes.iter().map(|&byte| byte as u32).collect()
+        }
+    }
 
-        *pixel = Rgba([
-            (base_r + (gradient_y * 30.0) as u8 + noise).saturating_sub(edge_darken),
-            (base_g + (gradient_y * 35.0) as u8 + noise).saturating_sub(edge_darken),
-            (base_b + (gradient_y * 40.0) as u8 + noise).saturating_sub(edge_darken),
-            255,
-        ]);
+    fn text(&self, code: u32) -> Option<&str> {
+        let mapped = self
+            .to_unicode
+            .as_ref()
+            .and_then(|map| map.get(code as u16));
+        match mapped {
+            Some(text) => Some(text),
+            None if !self.two_byte => self.simple[code as usize].as_deref(),
+            None => None,
+        }
     }
 
-    // Add a simple "PDF" text area in the center
-    let text_area_y = height / 2 - 40;
-    let text_area_height = 80;
-    let text_area_x = width / 4;
-    let text_area_width = width / 2;
+    /// Advance of a glyph in text space, where 1.0 is the font size.
+    fn width(&self, code: u32) -> f32 {
+        match self.widths.as_ref().map(|widths| widths.get(code as usize)) {
+            Some(width) if width > 0.0 => width / 1000.0,
+            // Without metrics, assume an average glyph.
+            _ => 0.5,
+        }
+    }
+}
+
+/// A run of text drawn at one position, in page space.
+#[derive(Debug, Clone, PartialEq)]
+struct Span {
+    x: f32,
+    y: f32,
+    end_x: f32,
+    size: f32,
+    text: String,
+}
 
-    // Create a lighter rectangle for text area
-    for y in text_area_y..(text_area_y + text_area_height).min(height) {
-        for x in text_area_x..(text_area_x + text_area_width).min(width) {
-            let pixel = img.get_pixel_mut(x, y);
-            *pixel = Rgba([200, 210, 220, 255]);
+#[derive(Clone)]
+struct GraphicsState {
+    ctm: Matrix,
+    font: Option<Rc<FontDecoder>>,
+    font_size: f32,
+    char_spacing: f32,
+    word_spacing: f32,
+    horizontal_scaling: f32,
+    leading: f32,
+    rise: f32,
+}
+
+impl Default for GraphicsState {
+    fn default() -> Self {
+        Self {
+            ctm: Matrix::default(),
+            font: None,
+            font_size: 0.0,
+            char_spacing: 0.0,
+            word_spacing: 0.0,
+            horizontal_scaling: 1.0,
+            leading: 0.0,
+            rise: 0.0,
         }
     }
+}
 
-    // Add a simple border around the text area
-    for y in text_area_y..(text_area_y + text_area_height).min(height) {
-        for x in [text_area_x, text_area_x + text_area_width - 1] {
-            if x < width {
-                let pixel = img.get_pixel_mut(x, y);
-                *pixel = Rgba([100, 110, 120, 255]);
+/// `first` applied, then `second`.
+fn concat(first: &Matrix, second: &Matrix) -> Matrix {
+    Matrix {
+        a: first.a * second.a + first.b * second.c,
+        b: first.a * second.b + first.b * second.d,
+        c: first.c * second.a + first.d * second.c,
+        d: first.c * second.b + first.d * second.d,
+        e: first.e * second.a + first.f * second.c + second.e,
+        f: first.e * second.b + first.f * second.d + second.f,
+    }
+}
+
+fn translation(x: f32, y: f32) -> Matrix {
+    Matrix {
+        e: x,
+        f: y,
+        ..Matrix::default()
+    }
+}
+
+fn apply(matrix: &Matrix, x: f32, y: f32) -> (f32, f32) {
+    (
+        matrix.a * x + matrix.c * y + matrix.e,
+        matrix.b * x + matrix.d * y + matrix.f,
+    )
+}
+
+/// Interprets content stream operators to find where text is drawn.
+struct TextExtractor<'a, R: Resolve> {
+    resolve: &'a R,
+    state: GraphicsState,
+    saved: Vec<GraphicsState>,
+    text_matrix: Matrix,
+    line_matrix: Matrix,
+    spans: Vec<Span>,
+}
+
+impl<'a, R: Resolve> TextExtractor<'a, R> {
+    fn new(resolve: &'a R) -> Self {
+        Self {
+            resolve,
+            state: GraphicsState::default(),
+            saved: Vec::new(),
+            text_matrix: Matrix::default(),
+            line_matrix: Matrix::default(),
+            spans: Vec::new(),
+        }
+    }
+
+    fn run(
+        &mut self,
+        operations: &[Op],
+        resources: &Resources,
+        depth: usize,
+    ) -> Result<(), PdfError> {
+        let mut fonts: HashMap<Name, Rc<FontDecoder>> = HashMap::new();
+        for operation in operations {
+            match operation {
+                Op::Save => self.saved.push(self.state.clone()),
+                Op::Restore => {
+                    if let Some(state) = self.saved.pop() {
+                        self.state = state;
+                    }
+                }
+                Op::Transform { matrix } => self.state.ctm = concat(matrix, &self.state.ctm),
+                Op::BeginText => {
+                    self.text_matrix = Matrix::default();
+                    self.line_matrix = Matrix::default();
+                }
+                Op::CharSpacing { char_space } => self.state.char_spacing = *char_space,
+                Op::WordSpacing { word_space } => self.state.word_spacing = *word_space,
+                Op::TextScaling { horiz_scale } => {
+                    self.state.horizontal_scaling = horiz_scale / 100.0
+                }
+                Op::Leading { leading } => self.state.leading = *leading,
+                Op::TextRise { rise } => self.state.rise = *rise,
+                Op::TextFont { name, size } => {
+                    self.state.font_size = *size;
+                    self.state.font = match fonts.get(name) {
+                        Some(font) => Some(font.clone()),
+                        None => resources.fonts.get(name).map(|font| {
+                            let decoder = Rc::new(FontDecoder::new(font, self.resolve));
+                            fonts.insert(name.clone(), decoder.clone());
+                            decoder
+                        }),
+                    };
+                }
+                Op::MoveTextPosition { translation: point } => {
+                    self.line_matrix = concat(&translation(point.x, point.y), &self.line_matrix);
+                    self.text_matrix = self.line_matrix;
+                }
+                Op::SetTextMatrix { matrix } => {
+                    self.line_matrix = *matrix;
+                    self.text_matrix = *matrix;
+                }
+                Op::TextNewline => {
+                    self.line_matrix =
+                        concat(&translation(0.0, -self.state.leading), &self.line_matrix);
+                    self.text_matrix = self.line_matrix;
+                }
+                Op::TextDraw { text } => self.show(text.as_bytes()),
+                Op::TextDrawAdjusted { array } => {
+                    for item in array {
+                        match item {
+                            TextDrawAdjusted::Text(text) => self.show(text.as_bytes()),
+                            TextDrawAdjusted::Spacing(adjustment) => self.advance(
+                                -adjustment / 1000.0
+                                    * self.state.font_size
+                                    * self.state.horizontal_scaling,
+                            ),
+                        }
+                    }
+                }
+                Op::XObject { name } if depth < MAX_FORM_DEPTH => {
+                    let Some(reference) = resources.xobjects.get(name) else {
+                        continue;
+                    };
+                    let xobject = self.resolve.get(*reference)?;
+                    if let XObject::Form(form) = &*xobject {
+                        let dict = form.dict();
+                        let form_operations = form.operations(self.resolve)?;
+                        let form_resources = dict.resources.as_deref().unwrap_or(resources);
+
+                        self.saved.push(self.state.clone());
+                        if let Some(matrix) = &dict.matrix {
+                            let matrix = Matrix::from_primitive(matrix.clone(), self.resolve)?;
+                            self.state.ctm = concat(&matrix, &self.state.ctm);
+                        }
+                        let (text_matrix, line_matrix) = (self.text_matrix, self.line_matrix);
+                        self.run(&form_operations, form_resources, depth + 1)?;
+                        self.text_matrix = text_matrix;
+                        self.line_matrix = line_matrix;
+                        if let Some(state) = self.saved.pop() {
+                            self.state = state;
+                        }
+                    }
+                }
+                _ => {}
             }
         }
+        Ok(())
+    }
+
+    /// Move the text position along the baseline by `tx` text space units.
+    fn advance(&mut self, tx: f32) {
+        self.text_matrix = concat(&translation(tx, 0.0), &self.text_matrix);
     }
-    for x in text_area_x..(text_area_x + text_area_width).min(width) {
-        for y in [text_area_y, text_area_y + text_area_height - 1] {
-            if y < height {
-                let pixel = img.get_pixel_mut(x, y);
-                *pixel = Rgba([100, 110, 120, 255]);
+
+    fn show(&mut self, bytes: &[u8]) {
+        let Some(font) = self.state.font.clone() else {
+            return;
+        };
+        let state = &self.state;
+        let start = concat(&self.text_matrix, &state.ctm);
+        let (x, y) = apply(&start, 0.0, state.rise);
+        let size = state.font_size * start.c.hypot(start.d);
+
+        let mut text = String::new();
+        let mut tx = 0.0;
+        for code in font.codes(bytes) {
+            if let Some(decoded) = font.text(code) {
+                text.push_str(decoded);
             }
+            let word_spacing = if code == 32 && !font.two_byte {
+                state.word_spacing
+            } else {
+                0.0
+            };
+            tx += (font.width(code) * state.font_size + state.char_spacing + word_spacing)
+                * state.horizontal_scaling;
+        }
+        self.advance(tx);
+
+        let end = concat(&self.text_matrix, &self.state.ctm);
+        let (end_x, _) = apply(&end, 0.0, self.state.rise);
+        if !text.is_empty() {
+            self.spans.push(Span {
+                x,
+                y,
+                end_x,
+                size,
+                text,
+            });
         }
     }
+}
 
-    // Encode as PNG
-    let mut buffer = Vec::new();
-    let mut cursor = Cursor::new(&mut buffer);
-    image::DynamicImage::ImageRgba8(img).write_to(&mut cursor, ImageFormat::Png)?;
+struct Line {
+    y: f32,
+    size: f32,
+    end_x: f32,
+    text: String,
+}
+
+/// Assemble spans, in the order they were drawn, into lines and paragraphs.
+/// Spans on one baseline form a line, with a space where there is a visible
+/// gap. A line starts a new paragraph after a gap larger than usual line
+/// spacing, a change of font size or a jump back up the page (a new column).
+fn layout(spans: &[Span]) -> String {
+    let mut lines: Vec<Line> = Vec::new();
+    for span in spans {
+        let text = normalize(&span.text);
+        let size = span.size.max(1.0);
+        if let Some(line) = lines.last_mut() {
+            let same_baseline = (span.y - line.y).abs() <= 0.5 * size.max(line.size);
+            if same_baseline && span.x >= line.end_x - size {
+                let gap = span.x - line.end_x;
+                if gap > 0.15 * size && !line.text.ends_with(' ') && !text.starts_with(' ') {
+                    line.text.push(' ');
+                }
+                line.text.push_str(&text);
+                line.end_x = line.end_x.max(span.end_x);
+                line.size = line.size.max(size);
+                continue;
+            }
+        }
+        lines.push(Line {
+            y: span.y,
+            size,
+            end_x: span.end_x,
+            text,
+        });
+    }
+
+    let mut text = String::new();
+    let mut previous: Option<&Line> = None;
+    for line in &lines {
+        let line_text = collapse_spaces(&line.text);
+        if line_text.is_empty() {
+            continue;
+        }
+        if let Some(previous) = previous {
+            let drop = previous.y - line.y;
+            let size_ratio = line.size.max(previous.size) / line.size.min(previous.size);
+            let same_paragraph =
+                drop > 0.0 && drop <= 1.8 * line.size.max(previous.size) && size_ratio < 1.3;
+            let hyphenated = text.ends_with('-')
+                && text.chars().rev().nth(1).is_some_and(char::is_alphabetic)
+                && line_text.starts_with(char::is_lowercase);
+            if same_paragraph && hyphenated {
+                text.pop();
+            } else if same_paragraph {
+                text.push('\n');
+            } else {
+                text.push_str("\n\n");
+            }
+        }
+        text.push_str(&line_text);
+        previous = Some(line);
+    }
+    text
+}
+
+/// Expand ligatures and drop characters that only affect rendering.
+fn normalize(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            'ﬀ' => normalized.push_str("ff"),
+            'ﬁ' => normalized.push_str("fi"),
+            'ﬂ' => normalized.push_str("fl"),
+            'ﬃ' => normalized.push_str("ffi"),
+            'ﬄ' => normalized.push_str("ffl"),
+            '\u{ad}' | '\u{200b}' | '\u{feff}' => {}
+            c if c.is_whitespace() => normalized.push(' '),
+            c if c.is_control() => {}
+            c => normalized.push(c),
+        }
+    }
+    normalized
+}
+
+fn collapse_spaces(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use expectest::prelude::*;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn span(x: f32, y: f32, end_x: f32, size: f32, text: &str) -> Span {
+        Span {
+            x,
+            y,
+            end_x,
+            size,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_page_texts_decode_the_sample_pdf() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/fixtures/sample.pdf");
+
+        let pages = Pdf::new(&path).page_texts().unwrap();
+
+        expect!(pages.len()).to(be_equal_to(1));
+        expect!(pages[0].page_number).to(be_equal_to(1));
+        let paragraphs: Vec<&str> = pages[0].text.split("\n\n").collect();
+        expect!(paragraphs[0]).to(be_equal_to("Sample PDF"));
+        // The MacRoman "fi" ligature comes out as plain letters.
+        expect!(paragraphs[1]).to(be_equal_to("This is a simple PDF file. Fun fun fun."));
+        expect!(
+            paragraphs[2].starts_with("Lorem ipsum dolor sit amet, consectetuer adipiscing elit.")
+        )
+        .to(be_true());
+    }
 
-    Ok(Cover::Fallback(buffer))
+    #[test]
+    fn test_layout_joins_lines_and_splits_paragraphs() {
+        let spans = [
+            span(72.0, 700.0, 140.0, 24.0, "Chapter"),
+            span(150.0, 700.0, 160.0, 24.0, "1"),
+            span(72.0, 660.0, 200.0, 12.0, "It was a bright cold"),
+            span(
+                72.0,
+                646.0,
+                200.0,
+                12.0,
+                "day in April, and the clocks were strik-",
+            ),
+            span(72.0, 632.0, 200.0, 12.0, "ing thirteen."),
+            span(72.0, 600.0, 200.0, 12.0, "Winston Smith hurried."),
+        ];
+
+        assert_eq!(
+            layout(&spans),
+            "Chapter 1\n\nIt was a bright cold\nday in April, and the clocks were striking thirteen.\n\nWinston Smith hurried."
+        );
+    }
+
+    #[test]
+    fn test_layout_treats_a_jump_up_the_page_as_a_new_column() {
+        let spans = [
+            span(72.0, 700.0, 250.0, 12.0, "End of the left column."),
+            span(320.0, 760.0, 500.0, 12.0, "Top of the right column."),
+        ];
+
+        expect!(layout(&spans)).to(be_equal_to(
+            "End of the left column.\n\nTop of the right column.".to_string(),
+        ));
+    }
+
+    /// A one-page PDF whose page draws a single Flate-compressed RGB image.
+    fn pdf_with_image(width: u32, height: u32, rgb: [u8; 3]) -> Vec<u8> {
+        use flate2::{write::ZlibEncoder, Compression};
+        use std::io::Write;
+
+        let samples: Vec<u8> = (0..width * height).flat_map(|_| rgb).collect();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&samples).unwrap();
+        let image = encoder.finish().unwrap();
+        let content = b"q 200 0 0 200 0 0 cm /Im1 Do Q";
+
+        let mut objects: Vec<Vec<u8>> = vec![
+            b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+            b"<< /Type /Pages /Kids [3 0 R] /Count 1 >>".to_vec(),
+            b"<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] /Resources << /XObject << /Im1 4 0 R >> >> /Contents 5 0 R >>".to_vec(),
+        ];
+        let mut image_object = format!(
+            "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /FlateDecode /Length {} >>\nstream\n",
+            width,
+            height,
+            image.len()
+        )
+        .into_bytes();
+        image_object.extend_from_slice(&image);
+        image_object.extend_from_slice(b"\nendstream");
+        objects.push(image_object);
+        let mut content_object = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        content_object.extend_from_slice(content);
+        content_object.extend_from_slice(b"\nendstream");
+        objects.push(content_object);
+
+        write_pdf(&objects)
+    }
+
+    /// A PDF whose objects are numbered from 1 in order, the first being
+    /// the catalog.
+    fn write_pdf(objects: &[Vec<u8>]) -> Vec<u8> {
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::new();
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            pdf.extend_from_slice(object);
+            pdf.extend_from_slice(b"\nendobj\n");
+        }
+        let xref = pdf.len();
+        pdf.extend_from_slice(
+            format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes(),
+        );
+        for offset in offsets {
+            pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+        }
+        pdf.extend_from_slice(
+            format!(
+                "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+                objects.len() + 1,
+                xref
+            )
+            .as_bytes(),
+        );
+        pdf
+    }
+
+    fn first_page_cover(path: &Path) -> Option<Vec<u8>> {
+        let file = FileOptions::cached().open(path).unwrap();
+        let page = file.get_page(0).unwrap();
+        let resolver = file.resolver();
+        page_cover(&page, &resolver)
+    }
+
+    #[test]
+    fn test_cover_is_the_image_on_the_first_page() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cover.pdf");
+        std::fs::write(&path, pdf_with_image(120, 160, [200, 30, 40])).unwrap();
+
+        let cover = first_page_cover(&path).expect("cover image");
+        let decoded = image::load_from_memory(&cover).unwrap().to_rgb8();
+
+        expect!(decoded.dimensions()).to(be_equal_to((120, 160)));
+        expect!(decoded.get_pixel(60, 80).0).to(be_equal_to([200, 30, 40]));
+    }
+
+    #[test]
+    fn test_cover_ignores_small_images_and_text_only_pages() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logo.pdf");
+        std::fs::write(&path, pdf_with_image(32, 32, [0, 0, 0])).unwrap();
+        let sample = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/fixtures/sample.pdf");
+
+        expect!(first_page_cover(&path)).to(be_none());
+        expect!(first_page_cover(&sample)).to(be_none());
+    }
+
+    #[test]
+    fn test_outline_items_resolve_to_page_numbers() {
+        let objects: Vec<Vec<u8>> = [
+            "<< /Type /Catalog /Pages 2 0 R /Outlines 5 0 R /Names << /Dests << /Names [(epilogue) [4 0 R /Fit]] >> >> >>",
+            "<< /Type /Pages /Kids [3 0 R 4 0 R] /Count 2 >>",
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>",
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 200 200] >>",
+            "<< /Type /Outlines /First 6 0 R /Last 8 0 R /Count 3 >>",
+            "<< /Title (Part  One) /Parent 5 0 R /Next 8 0 R /First 7 0 R /Last 7 0 R /Dest [3 0 R /XYZ 0 200 0] >>",
+            "<< /Title (Chapter 1) /Parent 6 0 R /A << /S /GoTo /D [4 0 R /Fit] >> >>",
+            "<< /Title (Epilogue) /Parent 5 0 R /Prev 6 0 R /Next 6 0 R /Dest (epilogue) >>",
+        ]
+        .iter()
+        .map(|object| object.as_bytes().to_vec())
+        .collect();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("outline.pdf");
+        std::fs::write(&path, write_pdf(&objects)).unwrap();
+
+        let toc = Pdf::new(&path).extract_toc().unwrap();
+
+        // The last item links back to the first; the cycle is cut.
+        assert_eq!(
+            toc,
+            vec![
+                TocEntry {
+                    title: "Part One".to_string(),
+                    page_number: Some(1),
+                    href: None,
+                    children: vec![TocEntry {
+                        title: "Chapter 1".to_string(),
+                        page_number: Some(2),
+                        href: None,
+                        children: Vec::new(),
+                    }],
+                },
+                TocEntry {
+                    title: "Epilogue".to_string(),
+                    page_number: Some(2),
+                    href: None,
+                    children: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_png_predictors_are_undone() {
+        // Two RGB pixels per row: a Sub-filtered row, then an Up-filtered row.
+        let data = [1, 10, 20, 30, 5, 5, 5, 2, 1, 1, 1, 1, 1, 1];
+
+        expect!(unpredict_png(&data, 6, 3))
+            .to(be_some().value(vec![10, 20, 30, 15, 25, 35, 11, 21, 31, 16, 26, 36]));
+    }
+
+    #[test]
+    fn test_gray_and_cmyk_samples_become_rgb() {
+        expect!(rgb_from(&[0, 0, 0, 0])).to(be_equal_to([255, 255, 255]));
+        expect!(rgb_from(&[255, 0, 0, 0])).to(be_equal_to([0, 255, 255]));
+        expect!(rgb_from(&[7])).to(be_equal_to([7, 7, 7]));
+    }
+
+    #[test]
+    fn test_glyph_names_map_to_unicode() {
+        expect!(glyph_name_to_unicode("quoteright")).to(be_equal_to(Some("’".to_string())));
+        expect!(glyph_name_to_unicode("eacute")).to(be_equal_to(Some("é".to_string())));
+        expect!(glyph_name_to_unicode("uni00410042")).to(be_equal_to(Some("AB".to_string())));
+        expect!(glyph_name_to_unicode("f_i")).to(be_equal_to(Some("fi".to_string())));
+        expect!(glyph_name_to_unicode("g123")).to(be_equal_to(None));
+    }
+
+    #[test]
+    fn test_base_encodings_cover_the_upper_half() {
+        expect!(base_encoding_char(&BaseEncoding::MacRomanEncoding, 0xDE)).to(be_some().value('ﬁ'));
+        expect!(base_encoding_char(&BaseEncoding::WinAnsiEncoding, 0x93)).to(be_some().value('“'));
+        expect!(base_encoding_char(&BaseEncoding::WinAnsiEncoding, 0xE9)).to(be_some().value('é'));
+        expect!(base_encoding_char(&BaseEncoding::WinAnsiEncoding, 0x81)).to(be_none());
+    }
 }
@@ -14,6 +14,16 @@ diesel::table! {
         version -> Integer,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        content_hash -> Nullable<Text>,
+        identifier -> Nullable<Text>,
+        language -> Nullable<Text>,
+        identifiers -> Text,
+        description -> Nullable<Text>,
+        subjects -> Text,
+        series -> Nullable<Text>,
+        series_index -> Nullable<Double>,
+        published -> Nullable<Text>,
+        contributors -> Text,
     }
 }
 
@@ -25,7 +35,75 @@ diesel::table! {
         data -> Text,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        chapterId -> Nullable<Integer>,
+        href -> Nullable<Text>,
+        startOffset -> Nullable<Integer>,
+        endOffset -> Nullable<Integer>,
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(books, chunk_data,);
+diesel::table! {
+    conversations (id) {
+        id -> Integer,
+        book_id -> Integer,
+        title -> Text,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    ingest_jobs (id) {
+        id -> Integer,
+        book_id -> Integer,
+        status -> Text,
+        total_pages -> Integer,
+        done_pages -> Integer,
+        last_page -> Nullable<Integer>,
+        error -> Nullable<Text>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    messages (id) {
+        id -> Integer,
+        conversation_id -> Integer,
+        role -> Text,
+        content -> Text,
+        citations -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    toc_entries (id) {
+        id -> Integer,
+        book_id -> Integer,
+        position -> Integer,
+        parent_position -> Nullable<Integer>,
+        level -> Integer,
+        title -> Text,
+        page_number -> Nullable<Integer>,
+        href -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    toc_extractions (book_id) {
+        book_id -> Integer,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::allow_tables_to_appear_in_same_query!(
+    books,
+    chunk_data,
+    conversations,
+    ingest_jobs,
+    messages,
+    toc_entries,
+    toc_extractions,
+);
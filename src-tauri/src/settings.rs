@@ -0,0 +1,58 @@
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tauri_plugin_store::StoreExt;
+
+use crate::http::{self, BackendSettings};
+use crate::library;
+use crate::llm_provider::{self, LlmSettings};
+
+const SETTINGS_KEY: &str = "settings";
+
+/// User-configurable app settings, persisted in `store.json`.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Settings {
+    /// Where questions about books are answered.
+    pub llm: LlmSettings,
+    /// The worker backing sign-in, speech, realtime and hosted answers.
+    pub backend: BackendSettings,
+    /// Folders whose books are imported as they appear.
+    pub watched_folders: Vec<PathBuf>,
+}
+
+pub fn load_settings(app: &tauri::AppHandle) -> anyhow::Result<Settings> {
+    let store = app.store("store.json")?;
+    match store.get(SETTINGS_KEY) {
+        Some(value) => Ok(serde_json::from_value(value)?),
+        None => Ok(Settings::default()),
+    }
+}
+
+/// Put the saved settings into effect; called once at startup.
+pub fn apply_saved_settings(app: &tauri::AppHandle) -> anyhow::Result<()> {
+    let settings = load_settings(app)?;
+    http::configure(&settings.backend)?;
+    llm_provider::use_provider(&settings.llm);
+    library::watch_folders(app, &settings.watched_folders);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_settings(app: tauri::AppHandle) -> Result<Settings, String> {
+    load_settings(&app).map_err(|e| e.to_string())
+}
+
+/// Save `settings` and put them into effect. Backend settings that can't
+/// be used are rejected before anything is saved; returns the watched
+/// folders that could not be watched.
+#[tauri::command]
+pub fn set_settings(app: tauri::AppHandle, settings: Settings) -> Result<Vec<PathBuf>, String> {
+    http::configure(&settings.backend).map_err(|e| e.to_string())?;
+    let store = app.store("store.json").map_err(|e| e.to_string())?;
+    store.set(SETTINGS_KEY, json!(settings));
+    store.save().map_err(|e| e.to_string())?;
+    llm_provider::use_provider(&settings.llm);
+    Ok(library::watch_folders(&app, &settings.watched_folders))
+}
@@ -1,35 +1,155 @@
-use serde_json::json;
-use tauri_plugin_store::StoreExt;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
 
-use crate::shared::types::BookData;
+use sha2::{Digest, Sha256};
+
+use crate::shared::types::{BookData, TocEntry};
 
 pub trait Extractable {
     fn extract(&self) -> Result<BookData, Box<dyn std::error::Error>>;
 }
 
-pub fn store_book_data(
-    app: tauri::AppHandle,
-    extractable: &impl Extractable,
-) -> Result<(), String> {
-    let store = app.store("store.json").map_err(|e| e.to_string())?;
-    let book_data = extractable.extract().map_err(|e| e.to_string())?;
-
-    match store.get("books") {
-        Some(value) => {
-            let mut current_books: Vec<BookData> =
-                serde_json::from_value(value.clone()).map_err(|e| e.to_string())?;
-            current_books.push(book_data.clone());
-            let books_value = serde_json::to_value(current_books).map_err(|e| e.to_string())?;
-            store.set("books", json!(books_value));
-            store.save().map_err(|e| e.to_string())?;
+/// Books whose table of contents can be read without rendering them.
+pub trait TocExtractable {
+    fn extract_toc(&self) -> Result<Vec<TocEntry>, Box<dyn std::error::Error>>;
+}
+
+/// Hex SHA-256 of a book file, read in blocks so large PDFs are never held
+/// in memory. Identifies the book wherever the file is moved to.
+pub fn content_hash(path: &Path) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub enum Cover {
+    Normal(Vec<u8>),
+    Fallback(Vec<u8>),
+}
+
+impl Display for Cover {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Cover::Normal(_) => write!(f, "normal"),
+            Cover::Fallback(_) => write!(f, "fallback"),
+        }
+    }
+}
+
+pub fn create_placeholder_cover() -> Result<Cover, Box<dyn std::error::Error>> {
+    use image::{ImageFormat, Rgba, RgbaImage};
+    use std::io::Cursor;
+
+    // Create a book-like placeholder cover (400x600 - typical book aspect ratio)
+    let width = 400u32;
+    let height = 600u32;
+
+    // Create a more book-like background with a subtle pattern
+    let mut img = RgbaImage::new(width, height);
+
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        // Create a subtle book-like texture
+        let gradient_y = (y as f32 / height as f32).min(1.0);
+        let _gradient_x = (x as f32 / width as f32).min(1.0);
+
+        // Base colors for a book cover look
+        let base_r = 45u8;
+        let base_g = 55u8;
+        let base_b = 70u8;
+
+        // Add some texture and variation
+        let noise = ((x + y) % 7) as u8 * 3;
+        let edge_darken = if x < 20 || x > width - 20 || y < 20 || y > height - 20 {
+            20
+        } else {
+            0
+        };
+
+        *pixel = Rgba([
+            (base_r + (gradient_y * 30.0) as u8 + noise).saturating_sub(edge_darken),
+            (base_g + (gradient_y * 35.0) as u8 + noise).saturating_sub(edge_darken),
+            (base_b + (gradient_y * 40.0) as u8 + noise).saturating_sub(edge_darken),
+            255,
+        ]);
+    }
+
+    // Add a simple "PDF" text area in the center
+    let text_area_y = height / 2 - 40;
+    let text_area_height = 80;
+    let text_area_x = width / 4;
+    let text_area_width = width / 2;
+
+    // Create a lighter rectangle for text area
+    for y in text_area_y..(text_area_y + text_area_height).min(height) {
+        for x in text_area_x..(text_area_x + text_area_width).min(width) {
+            let pixel = img.get_pixel_mut(x, y);
+            *pixel = Rgba([200, 210, 220, 255]);
         }
-        None => {
-            // No existing books, create new array
-            let current_books = vec![book_data.clone()];
-            let books_value = serde_json::to_value(current_books).map_err(|e| e.to_string())?;
-            store.set("books", json!(books_value));
-            store.save().map_err(|e| e.to_string())?;
+    }
+
+    // Add a simple border around the text area
+    for y in text_area_y..(text_area_y + text_area_height).min(height) {
+        for x in [text_area_x, text_area_x + text_area_width - 1] {
+            if x < width {
+                let pixel = img.get_pixel_mut(x, y);
+                *pixel = Rgba([100, 110, 120, 255]);
+            }
+        }
+    }
+    for x in text_area_x..(text_area_x + text_area_width).min(width) {
+        for y in [text_area_y, text_area_y + text_area_height - 1] {
+            if y < height {
+                let pixel = img.get_pixel_mut(x, y);
+                *pixel = Rgba([100, 110, 120, 255]);
+            }
         }
     }
-    Ok(())
+
+    // Encode as PNG
+    let mut buffer = Vec::new();
+    let mut cursor = Cursor::new(&mut buffer);
+    image::DynamicImage::ImageRgba8(img).write_to(&mut cursor, ImageFormat::Png)?;
+
+    Ok(Cover::Fallback(buffer))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use expectest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_content_hash_depends_on_content_not_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, content: &[u8]| {
+            let path = dir.path().join(name);
+            File::create(&path).unwrap().write_all(content).unwrap();
+            content_hash(&path).unwrap()
+        };
+        // Spans several read blocks.
+        let book = vec![b'a'; 200 * 1024];
+
+        let original = write("book.epub", &book);
+        let moved = write("moved.epub", &book);
+        let edited = write("edited.epub", &book[1..]);
+
+        expect!(write("empty.epub", b"")).to(be_equal_to(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855".to_string(),
+        ));
+        expect!(moved).to(be_equal_to(original.clone()));
+        expect!(edited).not_to(be_equal_to(original));
+    }
 }
@@ -16,16 +16,51 @@ impl BookKind {
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub struct BookData {
+    /// SHA-256 of the file.
     pub id: String,
-    kind: String,
-    cover: Vec<u8>,
-    title: Option<String>,
-    author: Option<String>,
-    publisher: Option<String>,
-    filepath: String,
-    location: String,
-    cover_kind: Option<String>,
-    version: u32,
+    pub kind: String,
+    pub cover: Vec<u8>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub publisher: Option<String>,
+    pub filepath: String,
+    pub location: String,
+    pub cover_kind: Option<String>,
+    pub version: u32,
+    /// Identifier the publisher gave the book, such as an ISBN.
+    pub identifier: Option<String>,
+    #[serde(default)]
+    pub metadata: BookMetadata,
+}
+
+/// What a book says about itself beyond its title, author and publisher.
+/// Only EPUBs carry most of it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BookMetadata {
+    /// Language tag, such as `en` or `pt-BR`.
+    pub language: Option<String>,
+    /// Every identifier as written in the book, such as ISBNs and UUIDs.
+    pub identifiers: Vec<String>,
+    pub description: Option<String>,
+    pub subjects: Vec<String>,
+    pub series: Option<String>,
+    /// Position in the series; may be fractional for novellas.
+    pub series_index: Option<f64>,
+    /// Publication date as written, usually `YYYY`, `YYYY-MM-DD` or a full
+    /// timestamp.
+    pub published: Option<String>,
+    pub contributors: Vec<Contributor>,
+}
+
+/// A creator or contributor of a book.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Contributor {
+    pub name: String,
+    /// MARC relator code, such as `aut` (author), `edt` (editor), `ill`
+    /// (illustrator) or `trl` (translator).
+    pub role: Option<String>,
 }
 
 impl BookData {
@@ -51,6 +86,31 @@ impl BookData {
             filepath,
             location: current_location,
             version: 0,
+            identifier: None,
+            metadata: BookMetadata::default(),
         }
     }
+
+    pub fn with_identifier(mut self, identifier: Option<String>) -> Self {
+        self.identifier = identifier;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: BookMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+}
+
+/// An entry of a book's table of contents, with the entries nested under it.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TocEntry {
+    pub title: String,
+    /// PDF page (from 1) or EPUB spine document (from 0) the entry points
+    /// to, numbered like the book's chunks.
+    pub page_number: Option<i32>,
+    /// EPUB target as a path inside the archive, with any fragment.
+    pub href: Option<String>,
+    pub children: Vec<TocEntry>,
 }
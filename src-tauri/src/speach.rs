@@ -1,7 +1,9 @@
 use serde_json::json;
 
+use crate::http;
+
 pub async fn tts(text: &str) -> anyhow::Result<Vec<u8>> {
-    let client = reqwest::Client::new();
+    let client = http::api_client()?;
 
     let map = json!({
         "voice": "alloy",
@@ -10,9 +12,7 @@ pub async fn tts(text: &str) -> anyhow::Result<Vec<u8>> {
         "speed": 1.0
     });
     let response = client
-        .post("https://rishi-worker.faridmato90.workers.dev/api/audio/speech")
-        .json(&map)
-        .send()
+        .send(client.post("/api/audio/speech").json(&map))
         .await
         .map_err(|e| anyhow::anyhow!("Failed to get response bytes: {}", e))?
         .bytes()
@@ -37,5 +37,6 @@ mod tests {
             audio_data.iter().take(12).collect::<Vec<&u8>>()
         );
         expect!(audio_data.len()).not_to(be_equal_to(0));
+        expect!(&audio_data[..3]).to(be_equal_to(&b"ID3"[..]));
     }
 }
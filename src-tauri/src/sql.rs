@@ -1,16 +1,27 @@
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
 
+use crate::chunker::{self, Chunk, ChunkerOptions, Section};
 use crate::commands::embed;
+use crate::conversation;
 use crate::db::DB_POOL;
-use crate::embed::{EmbedParam, EmbedResult, Metadata};
+use crate::embed::{EmbedParam, EmbedResult, Metadata, EMBEDDING_MODEL_ID};
+use crate::epub::Epub;
+use crate::ingest;
 use crate::models::{Books, ChunkData};
+use crate::pdf::Pdf;
 use crate::schema::{books, chunk_data};
-use crate::vectordb::{self, Vector};
+use crate::shared::books::content_hash;
+use crate::shared::types::{BookData, BookMetadata};
+use crate::toc;
+use crate::vectordb::{self, IndexConfig, Vector};
 use diesel::prelude::*;
+use diesel::sql_types::{BigInt, Integer, Nullable, Text};
 use serde::{Deserialize, Serialize};
 
 // Insertable structs for Diesel - must match schema field names (camelCase)
-#[derive(Insertable, Clone, Deserialize)]
+#[derive(Insertable, Clone, Default, Deserialize)]
 #[diesel(table_name = chunk_data)]
 #[serde(rename_all = "camelCase")]
 pub struct ChunkDataInsertable {
@@ -20,6 +31,16 @@ pub struct ChunkDataInsertable {
     #[diesel(column_name = bookId)]
     pub book_id: i32,
     pub data: String,
+    /// Position in `toc_entries` of the chapter the chunk belongs to.
+    #[diesel(column_name = chapterId)]
+    pub chapter_id: Option<i32>,
+    /// Spine document of an EPUB chunk.
+    pub href: Option<String>,
+    /// Character range of the chunk in its page or spine document.
+    #[diesel(column_name = startOffset)]
+    pub start_offset: Option<i32>,
+    #[diesel(column_name = endOffset)]
+    pub end_offset: Option<i32>,
 }
 
 #[derive(Insertable, Deserialize)]
@@ -36,6 +57,31 @@ pub struct BookInsertable {
     pub location: String,
     pub cover_kind: String,
     pub version: i32,
+    /// SHA-256 of the file; computed from `filepath` when not given.
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    /// Read from the file for EPUBs when not given.
+    #[serde(default)]
+    pub identifier: Option<String>,
+}
+
+impl From<BookData> for BookInsertable {
+    fn from(book: BookData) -> Self {
+        Self {
+            id: None,
+            kind: book.kind,
+            cover: book.cover,
+            title: book.title.unwrap_or_default(),
+            author: book.author.unwrap_or_default(),
+            publisher: book.publisher.unwrap_or_default(),
+            filepath: book.filepath,
+            location: book.location,
+            cover_kind: book.cover_kind.unwrap_or_default(),
+            version: book.version as i32,
+            content_hash: Some(book.id),
+            identifier: book.identifier,
+        }
+    }
 }
 
 // Serializable structs for Tauri commands
@@ -46,6 +92,32 @@ pub struct PageData {
     pub page_number: i32,
     pub book_id: i32,
     pub data: String,
+    pub chapter_id: Option<i32>,
+    pub href: Option<String>,
+    pub start_offset: Option<i32>,
+    pub end_offset: Option<i32>,
+}
+
+/// A passage returned by retrieval, with enough provenance to cite it and
+/// jump to it in the reader.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RetrievedPassage {
+    pub chunk_id: i64,
+    pub book_id: i32,
+    pub page_number: i32,
+    pub text: String,
+    /// Fused relevance score; higher is better.
+    pub score: f64,
+    /// Distance from the query in the vector index, if the passage was a
+    /// semantic match.
+    pub distance: Option<f32>,
+    /// Where the passage is, as in [`ChunkDataInsertable`], to jump to and
+    /// highlight it.
+    pub chapter_id: Option<i32>,
+    pub href: Option<String>,
+    pub start_offset: Option<i32>,
+    pub end_offset: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -61,6 +133,9 @@ pub struct Book {
     pub location: String,
     pub cover_kind: String,
     pub version: i32,
+    pub content_hash: Option<String>,
+    pub identifier: Option<String>,
+    pub metadata: BookMetadata,
 }
 
 impl From<Books> for Book {
@@ -76,10 +151,56 @@ impl From<Books> for Book {
             location: book.location,
             cover_kind: book.cover_kind,
             version: book.version,
+            content_hash: book.content_hash,
+            identifier: book.identifier,
+            // The lists are only written by `save_book_metadata`; should one
+            // not parse, the book is still shown without it.
+            metadata: BookMetadata {
+                language: book.language,
+                identifiers: serde_json::from_str(&book.identifiers).unwrap_or_default(),
+                description: book.description,
+                subjects: serde_json::from_str(&book.subjects).unwrap_or_default(),
+                series: book.series,
+                series_index: book.series_index,
+                published: book.published,
+                contributors: serde_json::from_str(&book.contributors).unwrap_or_default(),
+            },
         }
     }
 }
 
+#[derive(AsChangeset)]
+#[diesel(table_name = books)]
+#[diesel(treat_none_as_null = true)]
+struct BookMetadataChangeset {
+    language: Option<String>,
+    identifiers: String,
+    description: Option<String>,
+    subjects: String,
+    series: Option<String>,
+    series_index: Option<f64>,
+    published: Option<String>,
+    contributors: String,
+}
+
+impl BookMetadataChangeset {
+    fn new(metadata: &BookMetadata) -> Result<Self, String> {
+        let json = |value: serde_json::Result<String>| {
+            value.map_err(|e| format!("Failed to serialize book metadata: {}", e))
+        };
+        Ok(Self {
+            language: metadata.language.clone(),
+            identifiers: json(serde_json::to_string(&metadata.identifiers))?,
+            description: metadata.description.clone(),
+            subjects: json(serde_json::to_string(&metadata.subjects))?,
+            series: metadata.series.clone(),
+            series_index: metadata.series_index,
+            published: metadata.published.clone(),
+            contributors: json(serde_json::to_string(&metadata.contributors))?,
+        })
+    }
+}
+
 impl From<ChunkData> for PageData {
     fn from(chunk: ChunkData) -> Self {
         Self {
@@ -87,11 +208,239 @@ impl From<ChunkData> for PageData {
             page_number: chunk.page_number,
             book_id: chunk.book_id,
             data: chunk.data,
+            chapter_id: chunk.chapter_id,
+            href: chunk.href,
+            start_offset: chunk.start_offset,
+            end_offset: chunk.end_offset,
         }
     }
 }
 
+/// Directory under the app data dir holding each book's cached TTS audio.
+const TTS_CACHE_DIR: [&str; 2] = ["public", "tts-cache"];
+
+/// Name of the vector store holding a book's vectors.
+pub fn book_vector_store_name(book_id: impl std::fmt::Display) -> String {
+    format!("{}-vectordb", book_id)
+}
+
 // Internal helper functions
+/// Rank constant for reciprocal-rank fusion; 60 is the usual choice and keeps
+/// a single top hit from drowning out results both retrievers agree on.
+const RRF_K: f64 = 60.0;
+/// How many candidates each retriever contributes per requested passage.
+const CANDIDATES_PER_RESULT: usize = 3;
+
+#[derive(QueryableByName)]
+struct KeywordHit {
+    #[diesel(sql_type = BigInt)]
+    id: i64,
+}
+
+/// Turn free text into an FTS5 match expression. Double-quoted parts of the
+/// query are kept as phrases, every other word is matched on its own, and
+/// all terms are OR-ed so BM25 decides which chunks match best. Returns
+/// `None` when the query has no searchable words.
+fn fts_match_expression(query: &str) -> Option<String> {
+    let words = |text: &str| -> Vec<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+    let mut terms = Vec::new();
+    for (i, part) in query.split('"').enumerate() {
+        if i % 2 == 1 {
+            let phrase = words(part);
+            if !phrase.is_empty() {
+                terms.push(format!("\"{}\"", phrase.join(" ")));
+            }
+        } else {
+            terms.extend(words(part).into_iter().map(|word| format!("\"{}\"", word)));
+        }
+    }
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" OR "))
+    }
+}
+
+/// Chunk ids of `book_id` matching `query`, best BM25 match first. With
+/// `max_page`, only chunks on or before that page are considered.
+fn keyword_search(
+    book_id: i32,
+    query: &str,
+    limit: usize,
+    max_page: Option<i32>,
+) -> Result<Vec<i64>, String> {
+    let Some(expression) = fts_match_expression(query) else {
+        return Ok(Vec::new());
+    };
+
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection: {}", e))?;
+
+    let hits = diesel::sql_query(
+        "SELECT rowid AS id FROM chunk_fts \
+         WHERE chunk_fts MATCH ? AND bookId = ? \
+         AND (? IS NULL OR rowid IN \
+             (SELECT id FROM chunk_data WHERE bookId = ? AND pageNumber <= ?)) \
+         ORDER BY bm25(chunk_fts) LIMIT ?",
+    )
+    .bind::<Text, _>(expression)
+    .bind::<Integer, _>(book_id)
+    .bind::<Nullable<Integer>, _>(max_page)
+    .bind::<Integer, _>(book_id)
+    .bind::<Nullable<Integer>, _>(max_page)
+    .bind::<BigInt, _>(limit as i64)
+    .load::<KeywordHit>(&mut conn)
+    .map_err(|e| format!("Keyword search failed: {}", e))?;
+
+    Ok(hits.into_iter().map(|hit| hit.id).collect())
+}
+
+/// Ids of the chunks of `book_id` on or before `max_page`.
+fn chunk_ids_up_to(book_id: i32, max_page: i32) -> Result<HashSet<u64>, String> {
+    use crate::schema::chunk_data::dsl::*;
+
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection: {}", e))?;
+
+    let ids = chunk_data
+        .filter(bookId.eq(&book_id))
+        .filter(pageNumber.le(&max_page))
+        .select(id)
+        .load::<i64>(&mut conn)
+        .map_err(|e| format!("Failed to query page data: {}", e))?;
+
+    Ok(ids.into_iter().map(|chunk_id| chunk_id as u64).collect())
+}
+
+/// The last page (PDF, from 1) or spine document (EPUB, from 0) the reader
+/// has reached, from a stored `books.location`: a page number for PDFs and
+/// an EPUB CFI such as `epubcfi(/6/14[ch05]!/4/2/1:0)` for EPUBs, whose
+/// second step is the even, 1-based position of the spine item. `None` when
+/// the location can't be interpreted.
+pub fn reading_position(kind: &str, location: &str) -> Option<i32> {
+    let location = location.trim();
+    match kind {
+        "pdf" => location.parse().ok().filter(|page: &i32| *page >= 1),
+        "epub" => {
+            let path = location.strip_prefix("epubcfi(")?.split('!').next()?;
+            let step = path.split('/').nth(2)?;
+            let digits: String = step.chars().take_while(char::is_ascii_digit).collect();
+            let step: i32 = digits.parse().ok()?;
+            (step >= 2 && step % 2 == 0).then_some(step / 2 - 1)
+        }
+        _ => None,
+    }
+}
+
+/// How far into `book_id` retrieval may look: up to the reader's position
+/// when `spoiler_safe`, otherwise the whole book. A position that can't be
+/// read, such as that of a book that was just opened, counts as the start
+/// of the book.
+pub fn retrieval_limit(book_id: i32, spoiler_safe: bool) -> Result<Option<i32>, String> {
+    if !spoiler_safe {
+        return Ok(None);
+    }
+    let book = get_book(book_id)?.ok_or_else(|| format!("Book {} not found", book_id))?;
+    let start = if book.kind == "pdf" { 1 } else { 0 };
+    Ok(Some(
+        reading_position(&book.kind, &book.location).unwrap_or(start),
+    ))
+}
+
+/// Merge several rankings of chunk ids with reciprocal-rank fusion: each id
+/// scores `1 / (RRF_K + rank)` in every ranking it appears in. Returns ids
+/// with their fused scores, best first.
+fn reciprocal_rank_fusion(rankings: &[&[i64]]) -> Vec<(i64, f64)> {
+    let mut scores: HashMap<i64, f64> = HashMap::new();
+    for ranking in rankings {
+        for (rank, chunk_id) in ranking.iter().enumerate() {
+            *scores.entry(*chunk_id).or_default() += 1.0 / (RRF_K + rank as f64 + 1.0);
+        }
+    }
+    let mut fused: Vec<(i64, f64)> = scores.into_iter().collect();
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    fused
+}
+
+/// Load the chunks for `ranked` (chunk id, score) pairs as passages, in the
+/// given order. Ids without a chunk row are skipped.
+fn load_passages(
+    ranked: &[(i64, f64)],
+    distances: &HashMap<i64, f32>,
+) -> Result<Vec<RetrievedPassage>, String> {
+    use crate::schema::chunk_data::dsl::*;
+
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection: {}", e))?;
+
+    let chunk_ids: Vec<i64> = ranked.iter().map(|(chunk_id, _)| *chunk_id).collect();
+    let mut chunks: HashMap<i64, ChunkData> = chunk_data
+        .filter(id.eq_any(&chunk_ids))
+        .select(ChunkData::as_select())
+        .load::<ChunkData>(&mut conn)
+        .map_err(|e| format!("Failed to query page data: {}", e))?
+        .into_iter()
+        .map(|chunk| (chunk.id, chunk))
+        .collect();
+
+    Ok(ranked
+        .iter()
+        .filter_map(|(chunk_id, score)| {
+            let chunk = chunks.remove(chunk_id)?;
+            Some(RetrievedPassage {
+                chunk_id: chunk.id,
+                book_id: chunk.book_id,
+                page_number: chunk.page_number,
+                text: chunk.data,
+                score: *score,
+                distance: distances.get(chunk_id).copied(),
+                chapter_id: chunk.chapter_id,
+                href: chunk.href,
+                start_offset: chunk.start_offset,
+                end_offset: chunk.end_offset,
+            })
+        })
+        .collect())
+}
+
+/// Book indexes are tagged with the embedding model so a model change can be
+/// detected from the manifest.
+fn book_index_config() -> IndexConfig {
+    IndexConfig {
+        model_id: Some(EMBEDDING_MODEL_ID.to_string()),
+        ..Default::default()
+    }
+}
+
+fn embed_params_for(page_data: &[PageData]) -> Vec<EmbedParam> {
+    page_data
+        .iter()
+        .map(|item| EmbedParam {
+            text: item.data.clone(),
+            metadata: Metadata {
+                id: item.id as u64,
+                page_number: item.page_number as usize,
+                book_id: item.book_id as u32,
+                chapter_id: item.chapter_id,
+                href: item.href.clone(),
+                start: item.start_offset.map(|start| start as usize),
+                end: item.end_offset.map(|end| end as usize),
+            },
+        })
+        .collect()
+}
+
 fn has_saved_data(page_number: i32, book_id: i32) -> Result<bool, String> {
     use crate::schema::chunk_data::dsl::*;
 
@@ -123,20 +472,84 @@ pub fn save_page_data_many(page_data: Vec<ChunkDataInsertable>) -> Result<(), St
         .get()
         .map_err(|e| format!("Failed to get connection: {}", e))?;
 
+    conn.transaction(|conn| upsert_chunks(conn, &page_data))
+        .map_err(|e| format!("Failed to insert page data: {}", e))?;
+
+    Ok(())
+}
+
+/// Insert or update `page_data` by id, keeping the keyword index in step.
+/// Returns the ids of the saved chunks.
+fn upsert_chunks(
+    conn: &mut SqliteConnection,
+    page_data: &[ChunkDataInsertable],
+) -> QueryResult<Vec<i64>> {
     // For SQLite, insert items one by one with on_conflict handling
     // SQLite doesn't support batch inserts with on_conflict in the same way
     use crate::schema::chunk_data::dsl::*;
-    for item in &page_data {
-        diesel::insert_into(chunk_data)
-            .values(item)
-            .on_conflict(id)
-            .do_update()
-            .set(data.eq(diesel::dsl::sql::<diesel::sql_types::Text>("excluded.data")))
-            .execute(&mut conn)
-            .map_err(|e| format!("Failed to insert page data: {}", e))?;
-    }
+    page_data
+        .iter()
+        .map(|item| {
+            let chunk_id = diesel::insert_into(chunk_data)
+                .values(item)
+                .on_conflict(id)
+                .do_update()
+                .set((
+                    data.eq(diesel::dsl::sql::<Text>("excluded.data")),
+                    chapterId.eq(diesel::dsl::sql::<Nullable<Integer>>("excluded.chapterId")),
+                    href.eq(diesel::dsl::sql::<Nullable<Text>>("excluded.href")),
+                    startOffset.eq(diesel::dsl::sql::<Nullable<Integer>>(
+                        "excluded.startOffset",
+                    )),
+                    endOffset.eq(diesel::dsl::sql::<Nullable<Integer>>("excluded.endOffset")),
+                ))
+                .returning(id)
+                .get_result::<i64>(conn)?;
+
+            // Keep the keyword index in step with the chunk text.
+            diesel::sql_query("DELETE FROM chunk_fts WHERE rowid = ?")
+                .bind::<BigInt, _>(chunk_id)
+                .execute(conn)?;
+            diesel::sql_query("INSERT INTO chunk_fts (rowid, data, bookId) VALUES (?, ?, ?)")
+                .bind::<BigInt, _>(chunk_id)
+                .bind::<Text, _>(&item.data)
+                .bind::<Integer, _>(item.book_id)
+                .execute(conn)?;
+            Ok(chunk_id)
+        })
+        .collect()
+}
 
-    Ok(())
+/// Save the chunks of one page and delete the chunks saved for it before
+/// that aren't among them, such as chunks the frontend indexed under other
+/// ids or chunks of text that has since changed. Returns the deleted ids.
+fn replace_page_data(
+    page_number: i32,
+    book_id: i32,
+    page_data: &[ChunkDataInsertable],
+) -> Result<Vec<i64>, String> {
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection: {}", e))?;
+
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        let saved = upsert_chunks(conn, page_data)?;
+        let stale: Vec<i64> = chunk_data::table
+            .filter(chunk_data::bookId.eq(book_id))
+            .filter(chunk_data::pageNumber.eq(page_number))
+            .filter(chunk_data::id.ne_all(&saved))
+            .select(chunk_data::id)
+            .load(conn)?;
+        diesel::delete(chunk_data::table.filter(chunk_data::id.eq_any(&stale))).execute(conn)?;
+        for chunk_id in &stale {
+            diesel::sql_query("DELETE FROM chunk_fts WHERE rowid = ?")
+                .bind::<BigInt, _>(chunk_id)
+                .execute(conn)?;
+        }
+        Ok(stale)
+    })
+    .map_err(|e| format!("Failed to replace page data: {}", e))
 }
 
 #[tauri::command]
@@ -158,44 +571,265 @@ pub fn get_all_page_data_by_book_id(book_id: i32) -> Result<Vec<PageData>, Strin
     Ok(results.into_iter().map(PageData::from).collect())
 }
 
-#[tauri::command]
-pub fn save_book(book: BookInsertable) -> Result<Book, String> {
-    use crate::schema::books::dsl::*;
-
+/// Hash the files of books saved before books were identified by content,
+/// one at a time so no write lock is held while a file is read. Books whose
+/// file is missing stay unhashed until they are relinked. Returns how many
+/// books were hashed.
+pub fn backfill_content_hashes() -> Result<usize, String> {
     let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
     let mut conn = pool
         .get()
         .map_err(|e| format!("Failed to get connection: {}", e))?;
 
-    // Try to insert, but ignore conflicts on id
-    let result = diesel::insert_into(books)
-        .values(&book)
-        .on_conflict(id)
-        .do_nothing()
-        .execute(&mut conn);
-
-    match result {
-        Ok(_) => {
-            // If insert succeeded, get the inserted book
-            books
-                .filter(filepath.eq(&book.filepath))
-                .select(Books::as_select())
-                .first::<Books>(&mut conn)
-                .map(Book::from)
-                .map_err(|e| format!("Failed to get book: {}", e))
+    let unhashed: Vec<(i32, String)> = books::table
+        .filter(books::content_hash.is_null())
+        .select((books::id, books::filepath))
+        .load(&mut conn)
+        .map_err(|e| format!("Failed to query books: {}", e))?;
+    let mut hashed = 0;
+    for (book_id, path) in unhashed {
+        let Ok(hash) = content_hash(Path::new(&path)) else {
+            continue;
+        };
+        // Skip a book that was relinked (and hashed) meanwhile.
+        hashed += diesel::update(
+            books::table
+                .find(book_id)
+                .filter(books::content_hash.is_null()),
+        )
+        .set(books::content_hash.eq(hash))
+        .execute(&mut conn)
+        .map_err(|e| format!("Failed to save book hash: {}", e))?;
+    }
+    Ok(hashed)
+}
+
+/// The book `book` is another copy of: the same file, the same publisher
+/// identifier, or the same path.
+fn find_same_book(
+    conn: &mut SqliteConnection,
+    book: &BookInsertable,
+) -> QueryResult<Option<Books>> {
+    if let Some(hash) = &book.content_hash {
+        let found = books::table
+            .filter(books::content_hash.eq(hash))
+            .order_by(books::id.asc())
+            .select(Books::as_select())
+            .first::<Books>(conn)
+            .optional()?;
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
+    if let Some(identifier) = &book.identifier {
+        let found = books::table
+            .filter(books::identifier.eq(identifier))
+            .filter(books::kind.eq(&book.kind))
+            .order_by(books::id.asc())
+            .select(Books::as_select())
+            .first::<Books>(conn)
+            .optional()?;
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
+    books::table
+        .filter(books::filepath.eq(&book.filepath))
+        .select(Books::as_select())
+        .first::<Books>(conn)
+        .optional()
+}
+
+/// How [`store_book`] saved a book.
+enum SavedBook {
+    /// A book that was not in the library yet.
+    Inserted(Books),
+    /// A book imported before, now at this path with the same content.
+    Relinked(Books),
+    /// A book imported before whose file was replaced by another edition
+    /// with the same identifier. Its chunks and table of contents were
+    /// deleted; `indexed` tells whether there were any chunks.
+    Replaced { book: Books, indexed: bool },
+    /// Another copy of a book whose own file is still in place.
+    Duplicate(Books),
+}
+
+impl SavedBook {
+    fn book_mut(&mut self) -> &mut Books {
+        match self {
+            SavedBook::Inserted(book)
+            | SavedBook::Relinked(book)
+            | SavedBook::Replaced { book, .. }
+            | SavedBook::Duplicate(book) => book,
         }
-        Err(e) => {
-            // If conflict occurred, try to get existing book by filepath
-            books
-                .filter(filepath.eq(&book.filepath))
-                .select(Books::as_select())
-                .first::<Books>(&mut conn)
-                .map(Book::from)
-                .map_err(|_| format!("Failed to save or get book: {}", e))
+    }
+
+    fn into_book(self) -> Book {
+        match self {
+            SavedBook::Inserted(book)
+            | SavedBook::Relinked(book)
+            | SavedBook::Replaced { book, .. }
+            | SavedBook::Duplicate(book) => Book::from(book),
         }
     }
 }
 
+/// Fill in what identifies `book` and isn't given: the hash of its file
+/// and, for EPUBs, the publisher's identifier.
+fn identify(mut book: BookInsertable) -> BookInsertable {
+    let path = Path::new(&book.filepath);
+    if book.content_hash.is_none() {
+        book.content_hash = content_hash(path).ok();
+    }
+    if book.identifier.is_none() && book.kind == "epub" {
+        book.identifier = Epub::new(path).identifier();
+    }
+    book
+}
+
+fn insert_book(conn: &mut SqliteConnection, book: &BookInsertable) -> QueryResult<Books> {
+    // Try to insert, but ignore conflicts on id
+    let inserted = diesel::insert_into(books::table)
+        .values(book)
+        .on_conflict(books::id)
+        .do_nothing()
+        .returning(Books::as_returning())
+        .get_result::<Books>(conn)
+        .optional()?;
+    match inserted {
+        Some(inserted) => Ok(inserted),
+        None => books::table
+            .filter(books::filepath.eq(&book.filepath))
+            .select(Books::as_select())
+            .first::<Books>(conn),
+    }
+}
+
+/// Save an identified `book` within the caller's transaction; see [`save_book`].
+fn store_book(conn: &mut SqliteConnection, book: &BookInsertable) -> QueryResult<SavedBook> {
+    let Some(existing) = find_same_book(conn, book)? else {
+        return insert_book(conn, book).map(SavedBook::Inserted);
+    };
+    let same_content = match (&existing.content_hash, &book.content_hash) {
+        (Some(existing_hash), Some(hash)) => existing_hash == hash,
+        _ => existing.filepath == book.filepath,
+    };
+    if existing.filepath != book.filepath && Path::new(&existing.filepath).exists() {
+        // Another edition next to the one imported before is a book of its own.
+        return if same_content {
+            Ok(SavedBook::Duplicate(existing))
+        } else {
+            insert_book(conn, book).map(SavedBook::Inserted)
+        };
+    }
+
+    let relinked = diesel::update(books::table.find(existing.id))
+        .set((
+            books::filepath.eq(&book.filepath),
+            books::content_hash.eq(book
+                .content_hash
+                .as_ref()
+                .or(existing.content_hash.as_ref())),
+            books::identifier.eq(book.identifier.as_ref().or(existing.identifier.as_ref())),
+            books::updated_at.eq(diesel::dsl::now),
+        ))
+        .returning(Books::as_returning())
+        .get_result::<Books>(conn)?;
+    if same_content {
+        return Ok(SavedBook::Relinked(relinked));
+    }
+    // The old chunks describe text the new file doesn't have.
+    let indexed = delete_book_text(conn, relinked.id)? > 0;
+    ingest::delete_job_for_book(conn, relinked.id)?;
+    Ok(SavedBook::Replaced {
+        book: relinked,
+        indexed,
+    })
+}
+
+/// Finish saving a replaced book outside the transaction that saved it:
+/// delete the vectors and audio of its old file and, if the old file had
+/// been indexed, queue the new one for indexing.
+fn finish_replacing(saved: &SavedBook, app_data_dir: &Path) -> Result<(), String> {
+    let SavedBook::Replaced { book, indexed } = saved else {
+        return Ok(());
+    };
+    delete_book_files(book.id, app_data_dir)?;
+    if *indexed {
+        ingest::enqueue_ingest(book.id)?;
+    }
+    Ok(())
+}
+
+/// Save a newly imported book, or return the existing book if it was
+/// imported before. When the existing book's file has gone missing it is
+/// relinked to the new file, keeping its reading position, chunks and
+/// vectors; while it is still there the new file is just a duplicate.
+/// A file that only shares the book's identifier is another edition: it
+/// replaces a missing file, but its text is indexed afresh.
+pub fn save_book(book: BookInsertable, app_data_dir: &Path) -> Result<Book, String> {
+    store_book_with_metadata(book, None, app_data_dir)
+}
+
+/// Save a book read from its file together with its metadata; see
+/// [`save_book`] for books that were imported before. The metadata of a
+/// duplicate is left alone, as it was read from the other copy.
+pub fn save_book_data(book: BookData, app_data_dir: &Path) -> Result<Book, String> {
+    let metadata = BookMetadataChangeset::new(&book.metadata)?;
+    store_book_with_metadata(BookInsertable::from(book), Some(metadata), app_data_dir)
+}
+
+fn store_book_with_metadata(
+    book: BookInsertable,
+    metadata: Option<BookMetadataChangeset>,
+    app_data_dir: &Path,
+) -> Result<Book, String> {
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection: {}", e))?;
+
+    let book = identify(book);
+    let saved = conn
+        .transaction::<_, diesel::result::Error, _>(|conn| {
+            let mut saved = store_book(conn, &book)?;
+            if let Some(metadata) = &metadata {
+                if !matches!(saved, SavedBook::Duplicate(_)) {
+                    let book = saved.book_mut();
+                    *book = store_metadata(conn, book.id, metadata)?;
+                }
+            }
+            Ok(saved)
+        })
+        .map_err(|e| format!("Failed to save book: {}", e))?;
+    finish_replacing(&saved, app_data_dir)?;
+    Ok(saved.into_book())
+}
+
+fn store_metadata(
+    conn: &mut SqliteConnection,
+    book_id: i32,
+    metadata: &BookMetadataChangeset,
+) -> QueryResult<Books> {
+    diesel::update(books::table.find(book_id))
+        .set(metadata)
+        .returning(Books::as_returning())
+        .get_result::<Books>(conn)
+}
+
+/// Replace the stored metadata of a book.
+pub fn save_book_metadata(book_id: i32, metadata: &BookMetadata) -> Result<Book, String> {
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection: {}", e))?;
+
+    let changes = BookMetadataChangeset::new(metadata)?;
+    store_metadata(&mut conn, book_id, &changes)
+        .map(Book::from)
+        .map_err(|e| format!("Failed to save book metadata: {}", e))
+}
+
 #[tauri::command]
 pub fn get_book(book_id: i32) -> Result<Option<Book>, String> {
     use crate::schema::books::dsl::*;
@@ -232,18 +866,51 @@ pub fn get_books() -> Result<Vec<Book>, String> {
     Ok(results.into_iter().map(Book::from).collect())
 }
 
-#[tauri::command]
-pub fn delete_book(book_id: i32) -> Result<(), String> {
-    use crate::schema::books::dsl::*;
-
+/// Delete a book together with everything derived from it: its chunk rows,
+/// its vector index and any cached audio.
+pub fn delete_book(book_id: i32, app_data_dir: &Path) -> Result<(), String> {
     let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
     let mut conn = pool
         .get()
         .map_err(|e| format!("Failed to get connection: {}", e))?;
 
-    diesel::delete(books.filter(id.eq(&book_id)))
-        .execute(&mut conn)
-        .map_err(|e| format!("Failed to delete book: {}", e))?;
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        delete_book_text(conn, book_id)?;
+        conversation::delete_conversations_for_book(conn, book_id)?;
+        ingest::delete_job_for_book(conn, book_id)?;
+        diesel::delete(books::table.filter(books::id.eq(&book_id))).execute(conn)?;
+        Ok(())
+    })
+    .map_err(|e| format!("Failed to delete book: {}", e))?;
+
+    delete_book_files(book_id, app_data_dir)
+}
+
+/// Delete the chunks and table of contents read from a book's file.
+/// Returns how many chunks there were.
+fn delete_book_text(conn: &mut SqliteConnection, book_id: i32) -> QueryResult<usize> {
+    let chunks =
+        diesel::delete(chunk_data::table.filter(chunk_data::bookId.eq(&book_id))).execute(conn)?;
+    diesel::sql_query("DELETE FROM chunk_fts WHERE bookId = ?")
+        .bind::<Integer, _>(book_id)
+        .execute(conn)?;
+    toc::delete_toc_for_book(conn, book_id)?;
+    Ok(chunks)
+}
+
+/// Delete the vector index and cached audio made from a book's text.
+fn delete_book_files(book_id: i32, app_data_dir: &Path) -> Result<(), String> {
+    vectordb::delete_vector_store(app_data_dir.to_path_buf(), &book_vector_store_name(book_id))
+        .map_err(|e| format!("Failed to delete book vectors: {}", e))?;
+
+    let audio_cache_dir = TTS_CACHE_DIR
+        .iter()
+        .fold(app_data_dir.to_path_buf(), |dir, part| dir.join(part))
+        .join(book_id.to_string());
+    if audio_cache_dir.exists() {
+        fs::remove_dir_all(&audio_cache_dir)
+            .map_err(|e| format!("Failed to delete cached audio: {}", e))?;
+    }
 
     Ok(())
 }
@@ -275,7 +942,17 @@ pub async fn process_job(
     if has_saved_data(page_number, book_id)? {
         return Ok(());
     }
+    index_page(page_number, book_id, page_data, app_data_dir).await
+}
 
+/// Save and embed the chunks of one page, replacing whatever was indexed
+/// for the page before.
+pub async fn index_page(
+    page_number: i32,
+    book_id: i32,
+    page_data: Vec<ChunkDataInsertable>,
+    app_data_dir: &PathBuf,
+) -> Result<(), String> {
     if page_data.is_empty() {
         return Ok(());
     }
@@ -288,6 +965,10 @@ pub async fn process_job(
                 id: item.id.unwrap_or(0) as u64,
                 page_number: page_number as usize,
                 book_id: book_id as u32,
+                chapter_id: item.chapter_id,
+                href: item.href.clone(),
+                start: item.start_offset.map(|start| start as usize),
+                end: item.end_offset.map(|end| end as usize),
             };
             EmbedParam {
                 text: item.data.clone(),
@@ -297,7 +978,7 @@ pub async fn process_job(
         .collect();
 
     // Save page data first (ensures data is in DB even if embedding fails)
-    save_page_data_many(page_data.clone())?;
+    let stale = replace_page_data(page_number, book_id, &page_data)?;
 
     // Embed the text using the command
     let embed_results: Vec<EmbedResult> = embed(embed_params).await?;
@@ -320,12 +1001,133 @@ pub async fn process_job(
     // Save vectors using the existing command
 
     // save_vectors(app, &format!("{}-vectordb", book_id), dim, vectors)?;
-    let name = format!("{}-vectordb", book_id);
-    vectordb::save_vectors(vectors, app_data_dir.clone(), dim, &name).map_err(|e| e.to_string())?;
+    let name = book_vector_store_name(book_id);
+    vectordb::save_vectors(
+        vectors,
+        app_data_dir.clone(),
+        dim,
+        &name,
+        &book_index_config(),
+    )
+    .map_err(|e| e.to_string())?;
+    if !stale.is_empty() {
+        let stale: Vec<u64> = stale.into_iter().map(|id| id as u64).collect();
+        vectordb::remove_vectors(
+            app_data_dir.clone(),
+            dim,
+            &name,
+            &stale,
+            &book_index_config(),
+        )
+        .map_err(|e| e.to_string())?;
+    }
 
     Ok(())
 }
 
+/// Chunk a book's text on the Rust side and index every section that isn't
+/// indexed yet.
+pub async fn index_book_text(book_id: i32, app_data_dir: &PathBuf) -> Result<(), String> {
+    let chunks = book_chunks(book_id).await?;
+    process_chunks(book_id, chunks, app_data_dir).await
+}
+
+/// Extract and chunk a book's text, tagging each chunk with its chapter.
+pub async fn book_chunks(book_id: i32) -> Result<Vec<Chunk>, String> {
+    let book = get_book(book_id)?.ok_or_else(|| format!("Book {} not found", book_id))?;
+    let path = PathBuf::from(&book.filepath);
+    let sections = tokio::task::spawn_blocking(move || match book.kind.as_str() {
+        "epub" => chunker::epub_sections(&path).map_err(|e| e.to_string()),
+        "pdf" => Pdf::new(&path)
+            .page_texts()
+            .map(|pages| pages.into_iter().map(Section::from).collect())
+            .map_err(|e| e.to_string()),
+        kind => Err(format!(
+            "Text extraction is not supported for {} books",
+            kind
+        )),
+    })
+    .await
+    .map_err(|e| format!("Text extraction failed: {}", e))??;
+    let mut chunks = chunker::chunk_sections(book_id, &sections, &ChunkerOptions::default());
+    // Books without a usable table of contents are still indexed, just
+    // without chapters.
+    let toc = toc::get_book_toc(book_id).await.unwrap_or_default();
+    let chapters = toc::ChapterMap::new(&toc);
+    for chunk in &mut chunks {
+        chunk.chapter_id = chapters.chapter_at(chunk.page_number);
+    }
+    Ok(chunks)
+}
+
+/// Group chunks by page (or spine document), in page order.
+pub fn chunks_by_page(chunks: Vec<Chunk>) -> BTreeMap<i32, Vec<ChunkDataInsertable>> {
+    let mut pages: BTreeMap<i32, Vec<ChunkDataInsertable>> = BTreeMap::new();
+    for chunk in chunks {
+        pages
+            .entry(chunk.page_number)
+            .or_default()
+            .push(chunk.into());
+    }
+    pages
+}
+
+/// Save and embed chunks, one page (or spine document) at a time.
+pub async fn process_chunks(
+    book_id: i32,
+    chunks: Vec<Chunk>,
+    app_data_dir: &PathBuf,
+) -> Result<(), String> {
+    for (page_number, page_data) in chunks_by_page(chunks) {
+        process_job(page_number, book_id, page_data, app_data_dir).await?;
+    }
+    Ok(())
+}
+
+/// Whether the book's index was built by a different embedding model than
+/// the current one, so its search results would be meaningless.
+pub fn book_needs_reindex(book_id: i32, app_data_dir: &Path) -> Result<bool, String> {
+    vectordb::index_needs_reindex(
+        app_data_dir,
+        &book_vector_store_name(book_id),
+        EMBEDDING_MODEL_ID,
+    )
+    .map_err(|e| e.to_string())
+}
+
+/// Throw away the book's index and rebuild it from the saved chunks with the
+/// current embedding model.
+pub async fn reindex_book(book_id: i32, app_data_dir: &Path) -> Result<(), String> {
+    let name = book_vector_store_name(book_id);
+    vectordb::delete_vector_store(app_data_dir.to_path_buf(), &name).map_err(|e| e.to_string())?;
+
+    let page_data = get_all_page_data_by_book_id(book_id)?;
+    if page_data.is_empty() {
+        return Ok(());
+    }
+    let embed_results = embed(embed_params_for(&page_data)).await?;
+    let Some(first) = embed_results.first() else {
+        return Err("No embedding results returned".to_string());
+    };
+    let dim = first.embedding.len();
+    let vectors = embed_results
+        .iter()
+        .map(|result| Vector {
+            id: result.metadata.id,
+            vector: result.embedding.clone(),
+        })
+        .collect();
+    vectordb::save_vectors(
+        vectors,
+        app_data_dir.to_path_buf(),
+        dim,
+        &name,
+        &book_index_config(),
+    )
+    .map_err(|e| e.to_string())?;
+    vectordb::flush_vector_store(app_data_dir, &name).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub fn has_saved_epub_data(book_id: i32) -> Result<bool, String> {
     use crate::schema::chunk_data::dsl::*;
@@ -345,32 +1147,60 @@ pub fn has_saved_epub_data(book_id: i32) -> Result<bool, String> {
     Ok(result.is_some())
 }
 
+/// Find the passages of a book most relevant to `query_text`, fusing
+/// semantic (HNSW) and keyword (FTS5/BM25) results so exact names and
+/// quoted phrases are found as well as paraphrases. With `max_page` only
+/// passages on or before that page (or spine document) are returned, so
+/// answers can't give away what the reader hasn't reached yet.
 pub async fn get_context_for_query(
     query_text: String,
     book_id: u32,
     app_data_dir: &PathBuf,
     k: usize,
-) -> Result<Vec<String>, String> {
+    max_page: Option<i32>,
+) -> Result<Vec<RetrievedPassage>, String> {
+    let candidates = k * CANDIDATES_PER_RESULT;
     let embed_params = vec![EmbedParam {
-        text: query_text,
+        text: query_text.clone(),
         metadata: Metadata {
             id: 0,
             page_number: 0,
             book_id,
+            ..Default::default()
         },
     }];
     let embed_results = embed(embed_params).await?;
     let query = embed_results[0].embedding.clone();
     let dim = embed_results[0].embedding.len();
-    let name = format!("{}-vectordb", book_id);
-    let search_embeddings = vectordb::search_vectors(app_data_dir.clone(), dim, &name, query, k)
-        .map_err(|e| e.to_string())?;
-    // use the result to query the db for the actual text using the ids
-    let text = search_embeddings
+    let name = book_vector_store_name(book_id);
+    let allowed = match max_page {
+        Some(max_page) => Some(chunk_ids_up_to(book_id as i32, max_page)?),
+        None => None,
+    };
+    let search_embeddings = vectordb::search_vectors_filtered(
+        app_data_dir.clone(),
+        dim,
+        &name,
+        query,
+        candidates,
+        &book_index_config(),
+        allowed.as_ref(),
+    )
+    .map_err(|e| e.to_string())?;
+    let semantic_ids: Vec<i64> = search_embeddings
         .iter()
-        .map(|result| get_text_from_vector_id(result.id as i64))
-        .collect::<Result<Vec<String>, String>>()?;
-    Ok(text)
+        .map(|result| result.id as i64)
+        .collect();
+    let distances: HashMap<i64, f32> = search_embeddings
+        .iter()
+        .map(|result| (result.id as i64, result.distance))
+        .collect();
+    let keyword_ids = keyword_search(book_id as i32, &query_text, candidates, max_page)?;
+
+    // use the fused ranking to query the db for the passages themselves
+    let mut ranked = reciprocal_rank_fusion(&[&semantic_ids, &keyword_ids]);
+    ranked.truncate(k);
+    load_passages(&ranked, &distances)
 }
 
 #[tauri::command]
@@ -411,14 +1241,18 @@ pub fn get_text_from_vector_id(vector_id: i64) -> Result<String, String> {
 
 #[cfg(test)]
 mod tests {
+    use crate::shared::types::{BookData, BookMetadata, Contributor};
     use crate::test_fixtures;
     use crate::test_helpers::init_test_database_setup;
     use expectest::prelude::*;
     use pretty_assertions::assert_eq as pretty_assert_eq;
 
     use super::{
-        delete_book, get_all_page_data_by_book_id, get_book, save_book, save_page_data_many,
-        update_book_cover, BookInsertable, ChunkDataInsertable,
+        backfill_content_hashes, book_vector_store_name, delete_book, fts_match_expression,
+        get_all_page_data_by_book_id, get_book, keyword_search, process_job, reading_position,
+        reciprocal_rank_fusion, retrieval_limit, save_book, save_book_data, save_book_metadata,
+        save_page_data_many, update_book_cover, update_book_location, BookInsertable,
+        ChunkDataInsertable,
     };
 
     #[test]
@@ -433,12 +1267,14 @@ mod tests {
                 page_number: 1,
                 book_id,
                 data: "test".to_string(),
+                ..Default::default()
             },
             ChunkDataInsertable {
                 id: Some(2),
                 page_number: 2,
                 book_id,
                 data: "test2".to_string(),
+                ..Default::default()
             },
         ];
         save_page_data_many(page_data).unwrap();
@@ -452,6 +1288,100 @@ mod tests {
         expect!(result[1].data.as_str()).to(be_equal_to("test2"));
         Ok(())
     }
+    #[test]
+    fn test_keyword_search_finds_exact_names_and_tracks_updates() -> Result<(), String> {
+        let _setup = init_test_database_setup()?;
+        let book_id = 41;
+        let chunk = |chunk_id: i64, text: &str| ChunkDataInsertable {
+            id: Some(chunk_id),
+            page_number: 1,
+            book_id,
+            data: text.to_string(),
+            ..Default::default()
+        };
+        save_page_data_many(vec![
+            chunk(4101, "The ship sailed north for many days."),
+            chunk(4102, "Captain Ahab stood at the helm, staring at the sea."),
+            chunk(4103, "It was the best of times, it was the worst of times."),
+        ])?;
+
+        expect!(keyword_search(book_id, "Who is Ahab?", 5, None)?).to(be_equal_to(vec![4102]));
+        expect!(keyword_search(book_id, "\"worst of times\"", 5, None)?)
+            .to(be_equal_to(vec![4103]));
+        expect!(keyword_search(book_id + 1, "Ahab", 5, None)?.is_empty()).to(be_true());
+        // Nothing on the first page has been reached before it.
+        expect!(keyword_search(book_id, "Ahab", 5, Some(0))?.is_empty()).to(be_true());
+
+        // Re-saving a chunk replaces its indexed text.
+        save_page_data_many(vec![chunk(4102, "The first mate kept watch.")])?;
+        expect!(keyword_search(book_id, "Ahab", 5, None)?.is_empty()).to(be_true());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fts_match_expression_quotes_terms_and_keeps_phrases() {
+        expect!(fts_match_expression("Who is \"Mr. Darcy\"?")).to(be_equal_to(Some(
+            "\"Who\" OR \"is\" OR \"Mr Darcy\"".to_string(),
+        )));
+        expect!(fts_match_expression("?! \"\"")).to(be_equal_to(None));
+    }
+
+    #[test]
+    fn test_reading_position_reads_pdf_pages_and_epub_spine_steps() {
+        expect!(reading_position("pdf", "12")).to(be_some().value(12));
+        expect!(reading_position("pdf", "")).to(be_none());
+        expect!(reading_position("epub", "epubcfi(/6/14[ch05]!/4/2/1:0)")).to(be_some().value(6));
+        expect!(reading_position("epub", "epubcfi(/6/2!/4,/1:0,/3:5)")).to(be_some().value(0));
+        expect!(reading_position("epub", "0")).to(be_none());
+    }
+
+    #[test]
+    fn test_retrieval_limit_starts_at_the_beginning_when_the_position_is_unknown(
+    ) -> Result<(), String> {
+        let setup = init_test_database_setup()?;
+        let book = |kind: &str, location: &str| {
+            save_book(
+                BookInsertable {
+                    id: None,
+                    kind: kind.to_string(),
+                    cover: vec![],
+                    title: "Unread Book".to_string(),
+                    author: "Unread Author".to_string(),
+                    publisher: "Unread Publisher".to_string(),
+                    filepath: format!("/path/to/unread/{}-{}.{}", kind, location, kind),
+                    location: location.to_string(),
+                    cover_kind: "image/png".to_string(),
+                    version: 1,
+                    content_hash: None,
+                    identifier: None,
+                },
+                &setup.app_data_dir,
+            )
+            .map(|book| book.id)
+        };
+
+        let new_epub = book("epub", "1")?;
+        expect!(retrieval_limit(new_epub, true)?).to(be_some().value(0));
+        expect!(retrieval_limit(new_epub, false)?).to(be_none());
+        expect!(retrieval_limit(book("epub", "")?, true)?).to(be_some().value(0));
+        expect!(retrieval_limit(book("pdf", "")?, true)?).to(be_some().value(1));
+        expect!(retrieval_limit(book("pdf", "9")?, true)?).to(be_some().value(9));
+        Ok(())
+    }
+
+    #[test]
+    fn test_reciprocal_rank_fusion_prefers_ids_found_by_both() {
+        let semantic = [1, 2, 3];
+        let keyword = [3, 4];
+        pretty_assert_eq!(
+            reciprocal_rank_fusion(&[&semantic, &keyword])
+                .into_iter()
+                .map(|(chunk_id, _)| chunk_id)
+                .collect::<Vec<_>>(),
+            vec![3, 1, 2, 4]
+        );
+    }
+
     // embed data , save vectors and query the text from the vector id and fetch the text from the vector id to confirm
     #[tokio::test]
     async fn test_embed_data_and_save_vectors() -> Result<(), String> {
@@ -467,43 +1397,63 @@ mod tests {
                 page_number: (idx + 1) as i32,
                 book_id,
                 data: chunk.text.to_string(),
+                ..Default::default()
             })
             .collect();
 
         // Use temp directory for vectordb files
         let app_data_dir = &setup.app_data_dir;
 
-        use super::process_job;
         process_job(1, 1, page_data, app_data_dir).await?;
 
         // Test each chunk's related query to verify it returns the correct paragraph
         // Note: temp_dir stays alive for the entire test and is automatically cleaned up when it goes out of scope
         use super::get_context_for_query;
         for chunk in &test_chunks {
-            let results =
-                get_context_for_query(chunk.query.to_string(), book_id as u32, app_data_dir, 3)
-                    .await?;
+            let results = get_context_for_query(
+                chunk.query.to_string(),
+                book_id as u32,
+                app_data_dir,
+                3,
+                None,
+            )
+            .await?;
 
             // The query should return the related paragraph as the first result
             expect!(results.is_empty()).to(be_equal_to(false));
 
             // Use pretty_assertions for better diff display on failure
             pretty_assert_eq!(
-                results[0],
+                results[0].text,
                 chunk.text,
                 "Query '{}' should return the related paragraph, but got: '{}'",
                 chunk.query,
-                results[0]
+                results[0].text
             );
+            expect!(results[0].chunk_id).to(be_equal_to(chunk.id));
+            expect!(results[0].book_id).to(be_equal_to(book_id));
         }
 
+        // A reader on page 2 only gets passages from the first two pages.
+        let last = test_chunks.last().unwrap();
+        let results = get_context_for_query(
+            last.query.to_string(),
+            book_id as u32,
+            app_data_dir,
+            3,
+            Some(2),
+        )
+        .await?;
+        expect!(results.is_empty()).to(be_false());
+        expect!(results.iter().all(|passage| passage.page_number <= 2)).to(be_true());
+
         Ok(())
     }
 
     #[test]
     fn test_save_and_retrieve_book() -> Result<(), String> {
         // Initialize test database
-        let _setup = init_test_database_setup()?;
+        let setup = init_test_database_setup()?;
 
         // Create a test book with sensible data
         let test_book = BookInsertable {
@@ -517,10 +1467,12 @@ mod tests {
             location: "epubcfi(/6/4[chap01ref]!/4/2/2)".to_string(),
             cover_kind: "image/png".to_string(),
             version: 1,
+            content_hash: None,
+            identifier: None,
         };
 
         // Save the book
-        let saved_book = save_book(test_book)?;
+        let saved_book = save_book(test_book, &setup.app_data_dir)?;
 
         // Verify the book was saved with an ID
         expect!(saved_book.id).to(be_greater_than(0));
@@ -556,7 +1508,7 @@ mod tests {
     #[test]
     fn test_save_delete_and_verify_book_removed() -> Result<(), String> {
         // Initialize test database
-        let _setup = init_test_database_setup()?;
+        let setup = init_test_database_setup()?;
 
         // Create a test book with sensible data
         let test_book = BookInsertable {
@@ -570,10 +1522,12 @@ mod tests {
             location: "epubcfi(/6/4[chap02ref]!/4/2/2)".to_string(),
             cover_kind: "image/jpeg".to_string(),
             version: 2,
+            content_hash: None,
+            identifier: None,
         };
 
         // Save the book
-        let saved_book = save_book(test_book)?;
+        let saved_book = save_book(test_book, &setup.app_data_dir)?;
 
         // Verify the book was saved with an ID
         expect!(saved_book.id).to(be_greater_than(0));
@@ -587,7 +1541,7 @@ mod tests {
         expect!(book_before.title.as_str()).to(be_equal_to("Book To Delete"));
 
         // Delete the book
-        delete_book(book_id)?;
+        delete_book(book_id, &setup.app_data_dir)?;
 
         // Verify the book no longer exists
         let retrieved_book_after = get_book(book_id)?;
@@ -596,10 +1550,240 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_reimported_books_are_relinked_and_other_editions_reindexed() -> Result<(), String> {
+        let setup = init_test_database_setup()?;
+        let import = |name: &str, content: &str, identifier: Option<&str>| {
+            let path = setup.app_data_dir.join(name);
+            std::fs::write(&path, content).map_err(|e| e.to_string())?;
+            save_book(
+                BookInsertable {
+                    id: None,
+                    kind: "epub".to_string(),
+                    cover: vec![],
+                    title: "Relinked Book".to_string(),
+                    author: "Relink Author".to_string(),
+                    publisher: "Relink Publisher".to_string(),
+                    filepath: path.to_string_lossy().to_string(),
+                    location: "".to_string(),
+                    cover_kind: "image/png".to_string(),
+                    version: 1,
+                    content_hash: None,
+                    identifier: identifier.map(str::to_string),
+                },
+                &setup.app_data_dir,
+            )
+        };
+        let original = import("original.epub", "relinked book", None)?;
+        update_book_location(original.id, "epubcfi(/6/8!/4/2)".to_string())?;
+
+        // A second copy while the first is still there is a duplicate.
+        let copy = import("copy.epub", "relinked book", None)?;
+        expect!(copy.id).to(be_equal_to(original.id));
+        expect!(copy.filepath).to(be_equal_to(original.filepath.clone()));
+
+        // Once the first copy is gone the book follows the file.
+        std::fs::remove_file(&original.filepath).map_err(|e| e.to_string())?;
+        let moved = import("moved.epub", "relinked book", None)?;
+        expect!(moved.id).to(be_equal_to(original.id));
+        expect!(moved.filepath.ends_with("moved.epub")).to(be_true());
+        expect!(moved.location.as_str()).to(be_equal_to("epubcfi(/6/8!/4/2)"));
+
+        // Another edition next to the first is a book of its own.
+        let isbn = "urn:isbn:9780000000019";
+        let first = import("first.epub", "identified book", Some(isbn))?;
+        let second = import("second.epub", "identified book, revised", Some(isbn))?;
+        expect!(second.id).not_to(be_equal_to(first.id));
+        expect!(second.id).not_to(be_equal_to(original.id));
+
+        // Once the first is gone a third edition takes its place, but is
+        // indexed afresh instead of keeping the first edition's text.
+        save_page_data_many(vec![ChunkDataInsertable {
+            id: Some(9101),
+            page_number: 0,
+            book_id: first.id,
+            data: "first edition text".to_string(),
+            ..Default::default()
+        }])?;
+        crate::toc::save_toc(first.id, &[])?;
+        std::fs::remove_file(&first.filepath).map_err(|e| e.to_string())?;
+        let third = import("third.epub", "identified book, third edition", Some(isbn))?;
+        expect!(third.id).to(be_equal_to(first.id));
+        expect!(third.filepath.ends_with("third.epub")).to(be_true());
+        expect!(third.content_hash).not_to(be_equal_to(first.content_hash.clone()));
+        expect!(get_all_page_data_by_book_id(first.id)?.is_empty()).to(be_true());
+        expect!(crate::toc::get_toc(first.id)?).to(be_none());
+        let job = crate::ingest::get_ingest_jobs()?
+            .into_iter()
+            .find(|job| job.book_id == first.id)
+            .ok_or("the third edition was not queued")?;
+        expect!(job.status).to(be_equal_to(crate::ingest::IngestStatus::Queued));
+        Ok(())
+    }
+
+    #[test]
+    fn test_books_saved_before_hashing_are_hashed_by_the_backfill() -> Result<(), String> {
+        let setup = init_test_database_setup()?;
+        let path = setup.app_data_dir.join("unhashed.pdf");
+        let saved = save_book(
+            BookInsertable {
+                id: None,
+                kind: "pdf".to_string(),
+                cover: vec![],
+                title: "Unhashed Book".to_string(),
+                author: "Unhashed Author".to_string(),
+                publisher: "Unhashed Publisher".to_string(),
+                filepath: path.to_string_lossy().to_string(),
+                location: "1".to_string(),
+                cover_kind: "fallback".to_string(),
+                version: 1,
+                content_hash: None,
+                identifier: None,
+            },
+            &setup.app_data_dir,
+        )?;
+        expect!(saved.content_hash).to(be_none());
+
+        std::fs::write(&path, "unhashed book").map_err(|e| e.to_string())?;
+        backfill_content_hashes()?;
+        let hashed = get_book(saved.id)?.ok_or("book was not saved")?;
+        expect!(hashed.content_hash).to(be_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_book_metadata_round_trips_through_the_database() -> Result<(), String> {
+        let setup = init_test_database_setup()?;
+        let metadata = BookMetadata {
+            language: Some("en".to_string()),
+            identifiers: vec!["urn:uuid:metadata-test".to_string()],
+            description: Some("A whale hunt.".to_string()),
+            subjects: vec!["Whaling".to_string()],
+            series: Some("The Sea Novels".to_string()),
+            series_index: Some(2.5),
+            published: Some("1851".to_string()),
+            contributors: vec![Contributor {
+                name: "Rockwell Kent".to_string(),
+                role: Some("ill".to_string()),
+            }],
+        };
+        let book = BookData::new(
+            "metadata-test".to_string(),
+            "epub".to_string(),
+            vec![],
+            Some("Book With Metadata".to_string()),
+            None,
+            None,
+            "/path/to/metadata/book.epub".to_string(),
+            "".to_string(),
+            None,
+        )
+        .with_metadata(metadata.clone());
+
+        let saved = save_book_data(book, &setup.app_data_dir)?;
+
+        expect!(saved.metadata.clone()).to(be_equal_to(metadata.clone()));
+        let stored = get_book(saved.id)?.ok_or("book was not saved")?;
+        expect!(stored.metadata).to(be_equal_to(metadata));
+
+        save_book_metadata(saved.id, &BookMetadata::default())?;
+        let cleared = get_book(saved.id)?.ok_or("book was not saved")?;
+        expect!(cleared.metadata).to(be_equal_to(BookMetadata::default()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_duplicates_leave_the_saved_metadata_alone() -> Result<(), String> {
+        let setup = init_test_database_setup()?;
+        let import = |name: &str, language: &str| {
+            let path = setup.app_data_dir.join(name);
+            std::fs::write(&path, "book with its metadata").map_err(|e| e.to_string())?;
+            let book = BookData::new(
+                name.to_string(),
+                "pdf".to_string(),
+                vec![],
+                Some("Book With Its Metadata".to_string()),
+                None,
+                None,
+                path.to_string_lossy().to_string(),
+                "1".to_string(),
+                None,
+            )
+            .with_metadata(BookMetadata {
+                language: Some(language.to_string()),
+                ..Default::default()
+            });
+            save_book_data(book, &setup.app_data_dir)
+        };
+
+        let original = import("metadata-original.pdf", "en")?;
+        let copy = import("metadata-copy.pdf", "fr")?;
+
+        expect!(copy.id).to(be_equal_to(original.id));
+        expect!(copy.metadata.language).to(be_some().value("en".to_string()));
+        let stored = get_book(original.id)?.ok_or("book was not saved")?;
+        expect!(stored.metadata.language).to(be_some().value("en".to_string()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_delete_book_removes_chunks_vectors_and_audio() -> Result<(), String> {
+        let setup = init_test_database_setup()?;
+        let app_data_dir = &setup.app_data_dir;
+
+        let saved_book = save_book(
+            BookInsertable {
+                id: None,
+                kind: "epub".to_string(),
+                cover: vec![],
+                title: "Book With Derived Data".to_string(),
+                author: "Cascade Author".to_string(),
+                publisher: "Cascade Publisher".to_string(),
+                filepath: "/path/to/cascade/book.epub".to_string(),
+                location: "".to_string(),
+                cover_kind: "image/png".to_string(),
+                version: 1,
+                content_hash: None,
+                identifier: None,
+            },
+            &setup.app_data_dir,
+        )?;
+        let book_id = saved_book.id;
+
+        let page_data = vec![ChunkDataInsertable {
+            id: Some(9001),
+            page_number: 1,
+            book_id,
+            data: test_fixtures::get_test_chunks()[0].text.to_string(),
+            ..Default::default()
+        }];
+        process_job(1, book_id, page_data, app_data_dir).await?;
+        crate::vectordb::flush_vector_store(app_data_dir, &book_vector_store_name(book_id))
+            .map_err(|e| e.to_string())?;
+
+        let audio_dir = app_data_dir
+            .join("public")
+            .join("tts-cache")
+            .join(book_id.to_string());
+        std::fs::create_dir_all(&audio_dir).map_err(|e| e.to_string())?;
+        std::fs::write(audio_dir.join("cached.mp3"), [0u8; 4]).map_err(|e| e.to_string())?;
+
+        let index_file = app_data_dir.join(format!("{}-vectordb.hnsw.data", book_id));
+        expect!(index_file.exists()).to(be_equal_to(true));
+
+        delete_book(book_id, app_data_dir)?;
+
+        expect!(get_all_page_data_by_book_id(book_id)?.is_empty()).to(be_equal_to(true));
+        expect!(index_file.exists()).to(be_equal_to(false));
+        expect!(audio_dir.exists()).to(be_equal_to(false));
+
+        Ok(())
+    }
+
     #[test]
     fn test_update_book_cover() -> Result<(), String> {
         // Initialize test database
-        let _setup = init_test_database_setup()?;
+        let setup = init_test_database_setup()?;
 
         // Create a test book with an empty cover
         let test_book = BookInsertable {
@@ -613,10 +1797,12 @@ mod tests {
             location: "epubcfi(/6/4[chap03ref]!/4/2/2)".to_string(),
             cover_kind: "image/png".to_string(),
             version: 1,
+            content_hash: None,
+            identifier: None,
         };
 
         // Save the book
-        let saved_book = save_book(test_book)?;
+        let saved_book = save_book(test_book, &setup.app_data_dir)?;
         let book_id = saved_book.id;
 
         // Verify the book was saved with an empty cover
@@ -0,0 +1,190 @@
+// In-process stand-in for the Rishi worker, so tests never need the network.
+//
+// The server runs on its own thread and runtime for the whole test binary,
+// independent of the runtime of whichever test starts it first.
+
+use std::net::TcpListener as StdTcpListener;
+use std::sync::OnceLock;
+
+use serde_json::{json, Value};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Secret returned by `/api/realtime/client_secrets`.
+pub const MOCK_CLIENT_SECRET: &str = "ek_mock_client_secret";
+/// `/api/user/:state` answers 404 for states with this prefix, as the real
+/// worker does until sign-in completes.
+pub const PENDING_STATE_PREFIX: &str = "pending";
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn new(status: &'static str, content_type: &'static str, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            content_type,
+            body: body.into(),
+        }
+    }
+
+    fn json(value: Value) -> Self {
+        Self::new("200 OK", "application/json", value.to_string())
+    }
+
+    fn not_found() -> Self {
+        Self::new("404 Not Found", "text/plain", "Not found")
+    }
+}
+
+/// Base URL of the mock worker, starting it on first use.
+pub fn mock_worker_url() -> &'static str {
+    static URL: OnceLock<String> = OnceLock::new();
+    URL.get_or_init(|| {
+        let listener = StdTcpListener::bind("127.0.0.1:0").expect("Failed to bind mock worker");
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        listener.set_nonblocking(true).unwrap();
+        std::thread::Builder::new()
+            .name("mock-worker".to_string())
+            .spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .expect("Failed to start mock worker runtime");
+                runtime.block_on(serve(listener));
+            })
+            .expect("Failed to spawn mock worker");
+        url
+    })
+}
+
+async fn serve(listener: StdTcpListener) {
+    let listener = TcpListener::from_std(listener).expect("Failed to listen for mock worker");
+    loop {
+        if let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(handle_connection(stream));
+        }
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream) {
+    let Some((method, path, body)) = read_request(&mut stream).await else {
+        return;
+    };
+    let response = route(&method, &path, &body);
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    );
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(&response.body).await;
+    let _ = stream.shutdown().await;
+}
+
+/// Read one HTTP/1.1 request, returning its method, path and body.
+async fn read_request(stream: &mut TcpStream) -> Option<(String, String, Vec<u8>)> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let header_end = loop {
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).into_owned();
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let path = request_line.next()?.to_string();
+    let content_length = lines
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+        .unwrap_or(0);
+
+    let mut body = buffer.split_off(header_end);
+    while body.len() < content_length {
+        let read = stream.read(&mut chunk).await.ok()?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    Some((method, path, body))
+}
+
+fn route(method: &str, path: &str, body: &[u8]) -> Response {
+    let segments: Vec<&str> = path
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .trim_matches('/')
+        .split('/')
+        .collect();
+    match (method, segments.as_slice()) {
+        ("POST", ["api", "text", "completions"]) => completion(body),
+        ("POST", ["api", "audio", "speech"]) => Response::new("200 OK", "audio/mpeg", mock_mp3()),
+        ("GET", ["api", "user", state]) if state.starts_with(PENDING_STATE_PREFIX) => {
+            Response::not_found()
+        }
+        ("GET", ["api", "user", _]) => Response::json(mock_user("user_mock")),
+        ("GET", ["api", "clerk", "user", user_id]) => Response::json(mock_user(user_id)),
+        ("GET", ["api", "realtime", "client_secrets"]) => {
+            Response::new("200 OK", "text/plain", MOCK_CLIENT_SECRET)
+        }
+        _ => Response::not_found(),
+    }
+}
+
+/// A deterministic completion; streamed as server-sent events, one word per
+/// event, when the request asks for it.
+fn completion(body: &[u8]) -> Response {
+    let request: Value = serde_json::from_slice(body).unwrap_or_default();
+    let input = request["input"].as_str().unwrap_or_default();
+    let text = format!(
+        "This is a mock completion for a {}-character prompt.",
+        input.chars().count()
+    );
+    if !request["stream"].as_bool().unwrap_or(false) {
+        return Response::new("200 OK", "text/plain; charset=utf-8", text);
+    }
+    let mut events: String = text
+        .split_inclusive(' ')
+        .map(|word| format!("data: {}\n\n", json!({ "response": word })))
+        .collect();
+    events.push_str("data: [DONE]\n\n");
+    Response::new("200 OK", "text/event-stream", events)
+}
+
+fn mock_user(user_id: &str) -> Value {
+    json!({
+        "id": user_id,
+        "firstName": "Mock",
+        "lastName": "Reader",
+        "fullName": "Mock Reader",
+        "username": "mock-reader",
+        "imageUrl": null,
+        "hasImage": false,
+        "lastSignInAt": null,
+        "externalId": null,
+    })
+}
+
+/// An ID3 tag followed by a single silent MPEG frame header: enough for code
+/// that only checks it received audio bytes.
+fn mock_mp3() -> Vec<u8> {
+    let mut bytes = b"ID3\x04\x00\x00\x00\x00\x00\x00".to_vec();
+    bytes.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x64]);
+    bytes.extend(std::iter::repeat_n(0u8, 413));
+    bytes
+}
@@ -0,0 +1,272 @@
+use std::path::Path;
+
+use diesel::prelude::*;
+
+use crate::db::DB_POOL;
+use crate::epub::Epub;
+use crate::models::TocEntries;
+use crate::pdf::Pdf;
+use crate::schema::{toc_entries, toc_extractions};
+use crate::shared::books::TocExtractable;
+use crate::shared::types::TocEntry;
+use crate::sql;
+
+#[derive(Insertable)]
+#[diesel(table_name = toc_entries)]
+struct TocEntryInsertable<'a> {
+    book_id: i32,
+    position: i32,
+    parent_position: Option<i32>,
+    level: i32,
+    title: &'a str,
+    page_number: Option<i32>,
+    href: Option<&'a str>,
+}
+
+/// Flatten `entries` depth first, so every entry comes after its parent.
+fn flatten<'a>(
+    book_id: i32,
+    entries: &'a [TocEntry],
+    parent_position: Option<i32>,
+    level: i32,
+    rows: &mut Vec<TocEntryInsertable<'a>>,
+) {
+    for entry in entries {
+        let position = rows.len() as i32;
+        rows.push(TocEntryInsertable {
+            book_id,
+            position,
+            parent_position,
+            level,
+            title: &entry.title,
+            page_number: entry.page_number,
+            href: entry.href.as_deref(),
+        });
+        flatten(book_id, &entry.children, Some(position), level + 1, rows);
+    }
+}
+
+/// Rebuild the tree from rows ordered by position.
+fn unflatten(rows: Vec<TocEntries>) -> Vec<TocEntry> {
+    let mut entries: Vec<(Option<i32>, TocEntry)> = Vec::with_capacity(rows.len());
+    let mut positions = Vec::with_capacity(rows.len());
+    for row in rows {
+        positions.push(row.position);
+        entries.push((
+            row.parent_position,
+            TocEntry {
+                title: row.title,
+                page_number: row.page_number,
+                href: row.href,
+                children: Vec::new(),
+            },
+        ));
+    }
+    // Children come after their parent, so attaching from the end moves
+    // every subtree into place before its parent is moved.
+    let mut roots = Vec::new();
+    while let Some((parent_position, entry)) = entries.pop() {
+        positions.pop();
+        let parent = parent_position
+            .and_then(|parent| positions.binary_search(&parent).ok())
+            .map(|index| &mut entries[index].1);
+        match parent {
+            Some(parent) => parent.children.insert(0, entry),
+            None => roots.insert(0, entry),
+        }
+    }
+    roots
+}
+
+/// Which table of contents entry each page (or spine document) belongs to.
+pub struct ChapterMap {
+    /// First page of each chapter with the chapter's position in
+    /// `toc_entries`, by page.
+    starts: Vec<(i32, i32)>,
+}
+
+impl ChapterMap {
+    pub fn new(entries: &[TocEntry]) -> Self {
+        let mut rows = Vec::new();
+        flatten(0, entries, None, 0, &mut rows);
+        let mut starts: Vec<(i32, i32)> = rows
+            .iter()
+            .filter_map(|row| Some((row.page_number?, row.position)))
+            .collect();
+        // Of the entries starting on the same page, the outermost (or, for
+        // EPUB anchors into one document, the first) one wins.
+        starts.sort_by_key(|(page_number, _)| *page_number);
+        starts.dedup_by_key(|(page_number, _)| *page_number);
+        Self { starts }
+    }
+
+    /// The chapter `page_number` is part of: the last one starting at or
+    /// before it. Front matter before the first chapter has none.
+    pub fn chapter_at(&self, page_number: i32) -> Option<i32> {
+        let next = self
+            .starts
+            .partition_point(|(start, _)| *start <= page_number);
+        next.checked_sub(1).map(|index| self.starts[index].1)
+    }
+}
+
+/// Replace the stored table of contents of a book.
+pub fn save_toc(book_id: i32, entries: &[TocEntry]) -> Result<(), String> {
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection: {}", e))?;
+
+    let mut rows = Vec::new();
+    flatten(book_id, entries, None, 0, &mut rows);
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        delete_toc_for_book(conn, book_id)?;
+        if !rows.is_empty() {
+            diesel::insert_into(toc_entries::table)
+                .values(&rows)
+                .execute(conn)?;
+        }
+        diesel::insert_into(toc_extractions::table)
+            .values(toc_extractions::book_id.eq(book_id))
+            .execute(conn)?;
+        Ok(())
+    })
+    .map_err(|e| format!("Failed to save table of contents: {}", e))
+}
+
+/// The stored table of contents of a book; `None` if it was never extracted.
+pub fn get_toc(book_id: i32) -> Result<Option<Vec<TocEntry>>, String> {
+    let pool = DB_POOL.get().ok_or("Database pool not initialized")?;
+    let mut conn = pool
+        .get()
+        .map_err(|e| format!("Failed to get connection: {}", e))?;
+
+    let extracted = diesel::select(diesel::dsl::exists(
+        toc_extractions::table.filter(toc_extractions::book_id.eq(&book_id)),
+    ))
+    .get_result::<bool>(&mut conn)
+    .map_err(|e| format!("Failed to query table of contents: {}", e))?;
+    if !extracted {
+        return Ok(None);
+    }
+
+    let results = toc_entries::table
+        .filter(toc_entries::book_id.eq(&book_id))
+        .order_by(toc_entries::position.asc())
+        .select(TocEntries::as_select())
+        .load::<TocEntries>(&mut conn)
+        .map_err(|e| format!("Failed to query table of contents: {}", e))?;
+
+    Ok(Some(unflatten(results)))
+}
+
+/// Delete the table of contents of a book, as part of deleting the book.
+pub fn delete_toc_for_book(conn: &mut SqliteConnection, book_id: i32) -> QueryResult<()> {
+    diesel::delete(toc_entries::table.filter(toc_entries::book_id.eq(&book_id))).execute(conn)?;
+    diesel::delete(toc_extractions::table.filter(toc_extractions::book_id.eq(&book_id)))
+        .execute(conn)?;
+    Ok(())
+}
+
+/// The table of contents of a book, read from its file the first time and
+/// stored from then on.
+#[tauri::command]
+pub async fn get_book_toc(book_id: i32) -> Result<Vec<TocEntry>, String> {
+    if let Some(stored) = get_toc(book_id)? {
+        return Ok(stored);
+    }
+
+    let book = sql::get_book(book_id)?.ok_or_else(|| format!("Book {} not found", book_id))?;
+    let entries = tokio::task::spawn_blocking(move || {
+        let path = Path::new(&book.filepath);
+        match book.kind.as_str() {
+            "epub" => Epub::new(path).extract_toc().map_err(|e| e.to_string()),
+            "pdf" => Pdf::new(path).extract_toc().map_err(|e| e.to_string()),
+            kind => Err(format!(
+                "Tables of contents are not supported for {} books",
+                kind
+            )),
+        }
+    })
+    .await
+    .map_err(|e| format!("Table of contents extraction failed: {}", e))??;
+
+    save_toc(book_id, &entries)?;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_helpers::init_test_database_setup;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn entry(title: &str, page_number: i32, children: Vec<TocEntry>) -> TocEntry {
+        TocEntry {
+            title: title.to_string(),
+            page_number: Some(page_number),
+            href: None,
+            children,
+        }
+    }
+
+    #[test]
+    fn test_toc_round_trips_through_the_database() -> Result<(), String> {
+        let _setup = init_test_database_setup()?;
+        let book_id = 61;
+        let toc = vec![
+            entry(
+                "Part One",
+                1,
+                vec![
+                    entry("Chapter 1", 2, vec![entry("A Scene", 3, Vec::new())]),
+                    entry("Chapter 2", 5, Vec::new()),
+                ],
+            ),
+            TocEntry {
+                title: "Notes".to_string(),
+                page_number: None,
+                href: Some("notes.xhtml#n1".to_string()),
+                children: Vec::new(),
+            },
+        ];
+
+        assert_eq!(get_toc(book_id)?, None);
+        save_toc(book_id, &toc)?;
+        assert_eq!(get_toc(book_id)?, Some(toc.clone()));
+
+        save_toc(book_id, &toc[1..])?;
+        assert_eq!(get_toc(book_id)?, Some(toc[1..].to_vec()));
+
+        save_toc(book_id, &[])?;
+        assert_eq!(get_toc(book_id)?, Some(Vec::new()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pages_map_to_the_chapter_they_fall_in() {
+        let toc = vec![
+            entry(
+                "Part One",
+                3,
+                vec![
+                    entry("Chapter 1", 3, Vec::new()),
+                    entry("Chapter 2", 7, Vec::new()),
+                ],
+            ),
+            entry("Part Two", 12, Vec::new()),
+        ];
+
+        let chapters = ChapterMap::new(&toc);
+
+        let found: Vec<Option<i32>> = [1, 3, 6, 7, 11, 12, 40]
+            .into_iter()
+            .map(|page_number| chapters.chapter_at(page_number))
+            .collect();
+        assert_eq!(
+            found,
+            vec![None, Some(0), Some(0), Some(2), Some(2), Some(3), Some(3)]
+        );
+    }
+}
@@ -3,9 +3,11 @@ use hnsw_rs::hnswio::{HnswIo, ReloadOptions};
 use hnsw_rs::prelude::*;
 use serde::{Deserialize, Serialize};
 
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Vector {
@@ -23,171 +25,591 @@ impl Vector {
 pub struct SearchResult {
     pub id: u64,
     pub distance: f32,
+    /// Similarity derived from `distance`; higher is closer. See [`Metric::similarity`].
+    pub score: f32,
 }
 
 impl SearchResult {
-    pub fn new(id: u64, distance: f32) -> Self {
-        Self { id, distance }
+    pub fn new(id: u64, distance: f32, metric: Metric) -> Self {
+        Self {
+            id,
+            distance,
+            score: metric.similarity(distance),
+        }
+    }
+}
+
+/// Distance metric an index is built with. It is recorded next to the dump
+/// so the index is always reloaded with the metric it was built with.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum Metric {
+    L2,
+    #[default]
+    Cosine,
+    Dot,
+}
+
+impl Metric {
+    /// Map a raw distance under this metric to a similarity score:
+    /// cosine similarity for `Cosine`, the inner product for `Dot`, and
+    /// `1 / (1 + distance)` in `(0, 1]` for `L2`.
+    pub fn similarity(self, distance: f32) -> f32 {
+        match self {
+            Metric::L2 => 1.0 / (1.0 + distance),
+            Metric::Cosine | Metric::Dot => 1.0 - distance,
+        }
+    }
+}
+
+/// Inner-product distance `1 - <a, b>`.
+///
+/// `anndists::DistDot` asserts its inputs are unit vectors, which rounding
+/// alone can violate, so the inner product is computed without that check.
+#[derive(Default, Clone, Copy)]
+pub struct DistInnerProduct;
+
+impl Distance<f32> for DistInnerProduct {
+    fn eval(&self, va: &[f32], vb: &[f32]) -> f32 {
+        1.0 - va.iter().zip(vb).map(|(a, b)| a * b).sum::<f32>()
+    }
+}
+
+/// Object-safe view of an `Hnsw` index, so a store can hold an index built
+/// with any [`Metric`].
+trait AnnIndex: Send + Sync {
+    fn add(&self, vector: &[f32], id: DataId);
+    fn knn(
+        &self,
+        query: &[f32],
+        k: usize,
+        ef_search: usize,
+        filter: Option<&dyn FilterT>,
+    ) -> Vec<Neighbour>;
+    fn len(&self) -> usize;
+    /// Every stored vector with its id.
+    fn points(&self) -> Vec<(Vec<f32>, DataId)>;
+    fn dump(&self, directory: &Path, basename: &str) -> anyhow::Result<String>;
+}
+
+impl<D> AnnIndex for Hnsw<'static, f32, D>
+where
+    D: Distance<f32> + Send + Sync,
+{
+    fn add(&self, vector: &[f32], id: DataId) {
+        self.insert((vector, id));
+    }
+
+    fn knn(
+        &self,
+        query: &[f32],
+        k: usize,
+        ef_search: usize,
+        filter: Option<&dyn FilterT>,
+    ) -> Vec<Neighbour> {
+        self.search_filter(query, k, ef_search, filter)
+    }
+
+    fn len(&self) -> usize {
+        self.get_nb_point()
+    }
+
+    fn points(&self) -> Vec<(Vec<f32>, DataId)> {
+        points(self)
+    }
+
+    fn dump(&self, directory: &Path, basename: &str) -> anyhow::Result<String> {
+        self.file_dump(directory, basename)
+    }
+}
+
+/// Every vector stored in `hnsw` with its id.
+fn points<D>(hnsw: &Hnsw<'_, f32, D>) -> Vec<(Vec<f32>, DataId)>
+where
+    D: Distance<f32> + Send + Sync,
+{
+    if hnsw.get_nb_point() == 0 {
+        return Vec::new();
+    }
+    hnsw.get_point_indexation()
+        .into_iter()
+        .map(|point| (point.get_v().to_vec(), point.get_origin_id()))
+        .collect()
+}
+
+/// Version of the [`IndexManifest`] format written by this build.
+pub const MANIFEST_SCHEMA_VERSION: u32 = 1;
+
+const MAX_ELEMENTS: usize = 1_000_000;
+const MAX_NB_CONNECTION: usize = 16;
+const MAX_LAYER: usize = 16;
+const EF_CONSTRUCTION: usize = 200;
+
+/// Description of an index, written next to its HNSW dump so a reload can
+/// check that the index matches the embeddings it is about to be used with.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexManifest {
+    pub schema_version: u32,
+    /// Embedding model that produced the vectors, when known.
+    pub model_id: Option<String>,
+    pub dim: usize,
+    pub metric: Metric,
+    pub max_nb_connection: usize,
+    pub ef_construction: usize,
+    pub vector_count: usize,
+}
+
+/// Settings for opening a store. Unset fields fall back to what the existing
+/// index recorded, or to the defaults for a new index.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IndexConfig {
+    pub metric: Option<Metric>,
+    pub model_id: Option<String>,
+}
+/// How long a modified index may stay in memory before it is written back to disk.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// First point id handed out to a re-added vector whose own id is still
+/// held by its tombstoned previous vector. Ids from here up are reserved.
+const FIRST_STAND_IN: DataId = DataId::MAX / 2 + 1;
+
+/// An HNSW index kept in memory between inserts and searches.
+///
+/// HNSW has no delete operation, so removed and replaced points are
+/// tombstoned: searches skip them, and the index is rebuilt without them
+/// before it is written to disk or once they make up a quarter of it.
+struct ResidentIndex {
+    hnsw: Box<dyn AnnIndex>,
+    metric: Metric,
+    /// Live ids, each mapped to the point holding its current vector. That
+    /// point has the id itself, unless the id was re-added while its old
+    /// point was still in the graph; see `stand_ins`.
+    ids: HashMap<DataId, DataId>,
+    /// Points added under a stand-in id, mapped to the id they hold.
+    stand_ins: HashMap<DataId, DataId>,
+    next_stand_in: DataId,
+    tombstones: HashSet<DataId>,
+    dirty: bool,
+    last_flush: Instant,
+}
+
+impl ResidentIndex {
+    fn new(hnsw: Box<dyn AnnIndex>, metric: Metric) -> Self {
+        let ids = hnsw.points().into_iter().map(|(_, id)| (id, id)).collect();
+        Self {
+            hnsw,
+            metric,
+            ids,
+            stand_ins: HashMap::new(),
+            next_stand_in: FIRST_STAND_IN,
+            tombstones: HashSet::new(),
+            dirty: false,
+            last_flush: Instant::now(),
+        }
+    }
+
+    fn is_flush_due(&self) -> bool {
+        self.dirty && self.last_flush.elapsed() >= FLUSH_INTERVAL
+    }
+
+    /// The id held by `point`.
+    fn id_of(&self, point: DataId) -> DataId {
+        self.stand_ins.get(&point).copied().unwrap_or(point)
+    }
+
+    fn remove_ids(&mut self, ids: impl IntoIterator<Item = DataId>) {
+        for id in ids {
+            if let Some(point) = self.ids.remove(&id) {
+                self.tombstones.insert(point);
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// Add `vector` as the current vector of `id`, tombstoning its previous one.
+    fn add(&mut self, vector: &[f32], id: DataId) {
+        self.remove_ids([id]);
+        let point = if self.tombstones.contains(&id) {
+            let point = self.next_stand_in;
+            self.next_stand_in += 1;
+            self.stand_ins.insert(point, id);
+            point
+        } else {
+            id
+        };
+        self.hnsw.add(vector, point);
+        self.ids.insert(id, point);
+        self.dirty = true;
+    }
+
+    fn is_sparse(&self) -> bool {
+        self.tombstones.len() * 4 > self.hnsw.len()
+    }
+
+    /// Rebuild the index without its tombstoned points, giving every live
+    /// vector back its own id.
+    fn compact(&mut self) {
+        if self.tombstones.is_empty() {
+            return;
+        }
+        let compacted = VectorStore::create_new_index(self.metric);
+        for (vector, point) in self.hnsw.points() {
+            if !self.tombstones.contains(&point) {
+                compacted.add(&vector, self.id_of(point));
+            }
+        }
+        self.hnsw = compacted;
+        self.ids = self.ids.keys().map(|id| (*id, *id)).collect();
+        self.stand_ins.clear();
+        self.next_stand_in = FIRST_STAND_IN;
+        self.tombstones.clear();
+        self.dirty = true;
+    }
+
+    /// Dump the index next to the live files, then swap it in so a crash
+    /// mid-dump never leaves a half-written index behind. Returns the
+    /// manifest now on disk, or `None` if the index is empty and was removed.
+    fn flush(
+        &mut self,
+        directory: &Path,
+        basename: &str,
+        mut manifest: IndexManifest,
+    ) -> anyhow::Result<Option<IndexManifest>> {
+        self.compact();
+        let written = if self.hnsw.len() == 0 {
+            VectorStore::remove_dump(directory, basename)?;
+            None
+        } else {
+            fs::create_dir_all(directory)?;
+            let dump_name = self
+                .hnsw
+                .dump(directory, &format!("{}.flush", basename))
+                .map_err(|e| anyhow::anyhow!("Failed to save HNSW index: {}", e))?;
+            manifest.vector_count = self.ids.len();
+            VectorStore::write_manifest(directory, basename, &manifest)?;
+            VectorStore::replace_dump(directory, &dump_name, basename)?;
+            Some(manifest)
+        };
+        self.dirty = false;
+        self.last_flush = Instant::now();
+        Ok(written)
     }
 }
+
 pub struct VectorStore {
     pub dim: usize,
     pub directory: PathBuf,
     pub basename: String,
     pub ef_search: usize,
-    pub is_initialized: bool,
-    pub add_vector_queue: Vec<Vec<Vector>>,
+    pub metric: Metric,
+    /// Embedding model the caller produces vectors with, when known.
+    pub model_id: Option<String>,
+    /// Manifest of the index on disk; `None` for a new index or a dump
+    /// written before manifests existed.
+    manifest: Option<IndexManifest>,
+    index: Option<ResidentIndex>,
 }
 
 impl VectorStore {
-    pub fn new() -> anyhow::Result<Self> {
+    pub fn new(directory: PathBuf, dim: usize, basename: &str) -> anyhow::Result<Self> {
+        Self::open(directory, dim, basename, &IndexConfig::default())
+    }
+
+    /// Open a store, creating a new index from `config` if none exists yet.
+    /// An existing index keeps the metric it was built with; asking for a
+    /// different one is an error.
+    pub fn open(
+        directory: PathBuf,
+        dim: usize,
+        basename: &str,
+        config: &IndexConfig,
+    ) -> anyhow::Result<Self> {
+        fs::create_dir_all(&directory)?;
+        let manifest = Self::read_manifest(&directory, basename)?;
+        if let Some(manifest) = &manifest {
+            if manifest.schema_version > MANIFEST_SCHEMA_VERSION {
+                anyhow::bail!(
+                    "Index {} was written by a newer version (manifest schema {})",
+                    basename,
+                    manifest.schema_version
+                );
+            }
+        }
+        // Dumps written before manifests existed were always built with L2.
+        let stored_metric = match &manifest {
+            Some(manifest) => Some(manifest.metric),
+            None if Self::data_file_exists(&directory, basename) => Some(Metric::L2),
+            None => None,
+        };
+        let metric = match (stored_metric, config.metric) {
+            (Some(stored), Some(requested)) if stored != requested => anyhow::bail!(
+                "Index {} uses the {:?} metric, not {:?}",
+                basename,
+                stored,
+                requested
+            ),
+            (Some(stored), _) => stored,
+            (None, requested) => requested.unwrap_or_default(),
+        };
         Ok(Self {
-            dim: 0,
-            directory: PathBuf::from(""),
-            is_initialized: false,
-            basename: "".to_string(),
+            dim,
+            directory,
+            basename: basename.to_string(),
             ef_search: 50, // Default ef_search parameter
-            add_vector_queue: Vec::new(),
+            metric,
+            model_id: config.model_id.clone(),
+            manifest,
+            index: None,
         })
     }
-    pub fn init(&mut self, directory: PathBuf, dim: usize, basename: &str) -> anyhow::Result<()> {
-        fs::create_dir_all(&directory)?;
-        self.directory = directory;
-
-        self.dim = dim;
-        self.basename = basename.to_string();
-        self.is_initialized = true;
-        Ok(())
-    }
-
-    /// Get the full path to the data file (for checking existence)
-    // fn data_file_path(&self) -> PathBuf {
-    //     self.directory.join(format!("{}.hnsw.data", self.basename))
-    // }
 
     /// Helper function to create a new empty HNSW index
-    fn create_new_index<'a>() -> Hnsw<'a, f32, DistL2> {
-        let max_elements = 1_000_000;
-        let max_nb_connection = 16;
-        let max_layer = 16;
-        let ef_construction = 200;
-        Hnsw::new(
-            max_nb_connection,
-            max_elements,
-            max_layer,
-            ef_construction,
-            DistL2 {},
-        )
-    }
-
-    pub fn check_is_initialized(&self) -> anyhow::Result<()> {
-        if !self.is_initialized {
-            anyhow::bail!("Vector store is not initialized");
+    fn create_new_index(metric: Metric) -> Box<dyn AnnIndex> {
+        match metric {
+            Metric::L2 => Box::new(Hnsw::new(
+                MAX_NB_CONNECTION,
+                MAX_ELEMENTS,
+                MAX_LAYER,
+                EF_CONSTRUCTION,
+                DistL2 {},
+            )),
+            Metric::Cosine => Box::new(Hnsw::new(
+                MAX_NB_CONNECTION,
+                MAX_ELEMENTS,
+                MAX_LAYER,
+                EF_CONSTRUCTION,
+                DistCosine {},
+            )),
+            Metric::Dot => Box::new(Hnsw::new(
+                MAX_NB_CONNECTION,
+                MAX_ELEMENTS,
+                MAX_LAYER,
+                EF_CONSTRUCTION,
+                DistInnerProduct,
+            )),
         }
-        Ok(())
     }
 
-    /// Execute a closure with a loaded (or freshly created) HNSW index, keeping the reloader alive.
-    fn with_hnsw_mut<F, R>(
+    /// Load the dump for `basename` into memory, or create a fresh index if none exists.
+    fn load_index(
         directory: &Path,
         basename: &str,
-        mmap: bool,
-        mut f: F,
-    ) -> anyhow::Result<R>
+        metric: Metric,
+    ) -> anyhow::Result<Box<dyn AnnIndex>> {
+        if !Self::data_file_exists(directory, basename) {
+            return Ok(Self::create_new_index(metric));
+        }
+        match metric {
+            Metric::L2 => Self::load_dump::<DistL2>(directory, basename),
+            Metric::Cosine => Self::load_dump::<DistCosine>(directory, basename),
+            Metric::Dot => Self::load_dump::<DistInnerProduct>(directory, basename),
+        }
+    }
+
+    fn load_dump<D>(directory: &Path, basename: &str) -> anyhow::Result<Box<dyn AnnIndex>>
     where
-        F: FnMut(&mut Hnsw<f32, DistL2>) -> anyhow::Result<R>,
+        D: Distance<f32> + Default + Send + Sync + 'static,
     {
-        fs::create_dir_all(&directory)?;
-        if Self::data_file_exists(directory, basename) {
-            let mut reloader = HnswIo::new(directory, basename);
-            let options = ReloadOptions::default().set_mmap(mmap);
-            reloader.set_options(options);
-            let mut hnsw = reloader
-                .load_hnsw::<f32, DistL2>()
-                .map_err(|e| anyhow::anyhow!("Failed to load HNSW index: {}", e))?;
-            f(&mut hnsw)
-        } else {
-            let mut hnsw = Self::create_new_index();
-            f(&mut hnsw)
-        }
+        // The loaded index borrows from its reloader, so its points are
+        // copied into an index that owns them and the reloader is dropped
+        // once loading is done.
+        let mut reloader = HnswIo::new(directory, basename);
+        reloader.set_options(ReloadOptions::default().set_mmap(false));
+        let loaded = reloader
+            .load_hnsw::<f32, D>()
+            .map_err(|e| anyhow::anyhow!("Failed to load HNSW index: {}", e))?;
+        let points = points(&loaded);
+        let hnsw = Hnsw::<'static, f32, D>::new(
+            MAX_NB_CONNECTION,
+            MAX_ELEMENTS,
+            MAX_LAYER,
+            EF_CONSTRUCTION,
+            D::default(),
+        );
+        hnsw.parallel_insert(
+            &points
+                .iter()
+                .map(|(vector, id)| (vector, *id))
+                .collect::<Vec<_>>(),
+        );
+        Ok(Box::new(hnsw))
     }
-    pub fn add_vectors(&mut self, vectors: Vec<Vector>) -> anyhow::Result<()> {
-        self.add_vector_queue.push(vectors);
-        self.process_vectors()?;
+
+    fn manifest_file_path(directory: &Path, basename: &str) -> PathBuf {
+        directory.join(format!("{}.hnsw.manifest.json", basename))
+    }
+
+    fn write_manifest(
+        directory: &Path,
+        basename: &str,
+        manifest: &IndexManifest,
+    ) -> anyhow::Result<()> {
+        fs::write(
+            Self::manifest_file_path(directory, basename),
+            serde_json::to_vec_pretty(manifest)?,
+        )?;
         Ok(())
     }
-    /// Add a new vector to the store.
-    /// id must be unique — you manage this externally.
-    pub fn process_vectors(&mut self) -> anyhow::Result<()> {
-        // remove the first 5 vectors from the queue and process them
-        if self.add_vector_queue.is_empty() {
-            return Ok(());
-        }
-        let vectors = self
-            .add_vector_queue
-            .drain(..std::cmp::min(5, self.add_vector_queue.len()))
-            .flatten()
-            .collect::<Vec<_>>();
 
-        if vectors.iter().any(|v| v.vector.len() != self.dim) {
-            anyhow::bail!("Vector has wrong dimension: expected {}", self.dim,);
+    /// Read the manifest of the index named `basename`, if it has one.
+    pub fn read_manifest(
+        directory: &Path,
+        basename: &str,
+    ) -> anyhow::Result<Option<IndexManifest>> {
+        let manifest_path = Self::manifest_file_path(directory, basename);
+        if !manifest_path.exists() {
+            return Ok(None);
         }
-        self.check_is_initialized()?;
-        // Extract directory and basename to avoid borrow checker issues
-        let directory = self.directory.clone();
-        let basename = self.basename.clone();
-
-        let dump_name = Self::with_hnsw_mut(&directory, &basename, true, |hnsw| {
-            // Insert the vectors
-            for vector in &vectors {
-                hnsw.insert((&vector.vector, vector.id as usize));
-            }
+        let manifest = serde_json::from_slice(&fs::read(manifest_path)?)
+            .map_err(|e| anyhow::anyhow!("Invalid manifest for index {}: {}", basename, e))?;
+        Ok(Some(manifest))
+    }
 
-            hnsw.file_dump(&directory, &basename)
-                .map_err(|e| anyhow::anyhow!("Failed to save HNSW index: {}", e))
-        })?;
-        let mut old_basename = self.basename.clone();
+    /// Why the index on disk cannot be used with this store's embeddings, if
+    /// it can't: it was built by a different model or with a different
+    /// dimension. Such an index has to be rebuilt before it is searched.
+    pub fn reindex_reason(&self) -> Option<String> {
+        reindex_reason(self.manifest.as_ref()?, self.dim, self.model_id.as_deref())
+    }
 
-        self.overwrite_old_dump(&mut old_basename, &dump_name)?;
+    pub fn needs_reindex(&self) -> bool {
+        self.reindex_reason().is_some()
+    }
 
-        self.process_vectors()?;
+    fn ensure_compatible(&self) -> anyhow::Result<()> {
+        match self.reindex_reason() {
+            Some(reason) => anyhow::bail!("Index {} needs reindexing: {}", self.basename, reason),
+            None => Ok(()),
+        }
+    }
 
-        Ok(())
+    /// The resident index, loading it on first use.
+    fn resident_index(&mut self) -> anyhow::Result<&mut ResidentIndex> {
+        if self.index.is_none() {
+            let hnsw = Self::load_index(&self.directory, &self.basename, self.metric)?;
+            self.index = Some(ResidentIndex::new(hnsw, self.metric));
+        }
+        self.index
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("Failed to load HNSW index"))
     }
-    pub fn overwrite_old_dump(
-        &mut self,
-        old_basename: &str,
-        new_dump_name: &str,
-    ) -> anyhow::Result<()> {
-        if old_basename == new_dump_name {
+
+    /// Add vectors to the store.
+    /// ids must be unique — you manage this externally.
+    pub fn add_vectors(&mut self, vectors: Vec<Vector>) -> anyhow::Result<()> {
+        if vectors.is_empty() {
             return Ok(());
         }
-        let directory = self.directory.clone();
-        let old_dump_path = directory.join(format!("{}.hnsw.data", old_basename));
-        let old_graph_path = directory.join(format!("{}.hnsw.graph", old_basename));
+        if vectors.iter().any(|v| v.vector.len() != self.dim) {
+            anyhow::bail!("Vector has wrong dimension: expected {}", self.dim,);
+        }
+        if let Some(vector) = vectors.iter().find(|v| v.id as DataId >= FIRST_STAND_IN) {
+            anyhow::bail!("Vector id {} is out of range", vector.id);
+        }
+        self.ensure_compatible()?;
+        let index = self.resident_index()?;
 
-        let new_dump_path = directory.join(format!("{}.hnsw.data", new_dump_name));
-        let new_graph_path = directory.join(format!("{}.hnsw.graph", new_dump_name));
-        if !old_dump_path.exists() {
-            return Ok(());
+        // Re-adding an id replaces its old vector rather than duplicating it.
+        for vector in &vectors {
+            index.add(&vector.vector, vector.id as DataId);
+        }
+        if index.is_sparse() {
+            index.compact();
+        }
+        if index.is_flush_due() {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Remove vectors by id. Removed ids stop appearing in search results
+    /// immediately and are dropped from the index on the next flush.
+    pub fn remove_ids(&mut self, ids: &[u64]) -> anyhow::Result<()> {
+        let index = self.resident_index()?;
+        index.remove_ids(ids.iter().map(|id| *id as DataId));
+        if index.is_sparse() {
+            index.compact();
         }
+        Ok(())
+    }
+
+    /// Write the resident index and its manifest back to disk if it has been modified.
+    pub fn flush(&mut self) -> anyhow::Result<()> {
+        let Some(index) = self.index.as_mut().filter(|index| index.dirty) else {
+            return Ok(());
+        };
+        let manifest = IndexManifest {
+            schema_version: MANIFEST_SCHEMA_VERSION,
+            // Keep the recorded model when this caller doesn't know which one it uses.
+            model_id: self.model_id.clone().or_else(|| {
+                self.manifest
+                    .as_ref()
+                    .and_then(|manifest| manifest.model_id.clone())
+            }),
+            dim: self.dim,
+            metric: self.metric,
+            max_nb_connection: MAX_NB_CONNECTION,
+            ef_construction: EF_CONSTRUCTION,
+            vector_count: 0,
+        };
+        self.manifest = index.flush(&self.directory, &self.basename, manifest)?;
+        Ok(())
+    }
 
-        fs::rename(new_dump_path, old_dump_path)?;
-        if !old_graph_path.exists() {
+    /// Rename the `from` dump files over the `to` dump files.
+    fn replace_dump(directory: &Path, from: &str, to: &str) -> anyhow::Result<()> {
+        if from == to {
             return Ok(());
         }
-        fs::rename(new_graph_path, old_graph_path)?;
+        for extension in ["hnsw.data", "hnsw.graph"] {
+            fs::rename(
+                directory.join(format!("{}.{}", from, extension)),
+                directory.join(format!("{}.{}", to, extension)),
+            )?;
+        }
+        Ok(())
+    }
 
+    /// Remove the dump files for `basename`, including any interrupted flush.
+    fn remove_dump(directory: &Path, basename: &str) -> anyhow::Result<()> {
+        for name in [basename.to_string(), format!("{}.flush", basename)] {
+            for extension in ["hnsw.data", "hnsw.graph", "hnsw.manifest.json"] {
+                let path = directory.join(format!("{}.{}", name, extension));
+                if path.exists() {
+                    fs::remove_file(path)?;
+                }
+            }
+        }
         Ok(())
     }
 
+    /// Drop the resident index and delete everything this store has on disk.
+    pub fn destroy(&mut self) -> anyhow::Result<()> {
+        self.index = None;
+        self.manifest = None;
+        Self::remove_dump(&self.directory, &self.basename)
+    }
+
     /// Helper to check if data file exists
     fn data_file_exists(directory: &Path, basename: &str) -> bool {
         directory.join(format!("{}.hnsw.data", basename)).exists()
     }
 
     /// Search the top-k nearest vectors
-    pub fn search(&self, query: Vec<f32>, k: usize) -> anyhow::Result<Vec<SearchResult>> {
+    pub fn search(&mut self, query: Vec<f32>, k: usize) -> anyhow::Result<Vec<SearchResult>> {
+        self.search_filtered(query, k, None)
+    }
+
+    /// Search the top-k nearest vectors, only considering ids in `allowed`
+    /// when it is given. Filtering happens during the graph search, so a
+    /// small allowed set still yields up to k results.
+    pub fn search_filtered(
+        &mut self,
+        query: Vec<f32>,
+        k: usize,
+        allowed: Option<&HashSet<u64>>,
+    ) -> anyhow::Result<Vec<SearchResult>> {
         if query.len() != self.dim {
             anyhow::bail!(
                 "Query vector has wrong dimension: expected {}, got {}",
@@ -195,21 +617,25 @@ impl VectorStore {
                 query.len()
             );
         }
-        self.check_is_initialized()?;
-        // Extract directory and basename to avoid borrow checker issues
-        let directory = self.directory.clone();
-        let basename = self.basename.clone();
+        self.ensure_compatible()?;
         let ef_search = self.ef_search;
+        let metric = self.metric;
+        let index = self.resident_index()?;
+        let neighbours = if index.tombstones.is_empty() && allowed.is_none() {
+            index.hnsw.knn(&query, k, ef_search, None)
+        } else {
+            let index = &*index;
+            let filter = |point: &DataId| {
+                !index.tombstones.contains(point)
+                    && allowed.is_none_or(|allowed| allowed.contains(&(index.id_of(*point) as u64)))
+            };
+            index.hnsw.knn(&query, k, ef_search, Some(&filter))
+        };
 
-        // Load or create the index and perform search while the reloader stays alive.
-        let neighbours = Self::with_hnsw_mut(&directory, &basename, true, |hnsw| {
-            Ok(hnsw.search(&query, k, ef_search))
-        })?;
-
-        // Convert Vec<Neighbour> to Vec<(u32, f32)>
+        // Convert Vec<Neighbour> to Vec<SearchResult>
         let results = neighbours
             .into_iter()
-            .map(|n| SearchResult::new(n.d_id as u64, n.distance))
+            .map(|n| SearchResult::new(index.id_of(n.d_id) as u64, n.distance, metric))
             .collect::<Vec<_>>();
 
         Ok(results)
@@ -221,38 +647,152 @@ impl VectorStore {
     }
 }
 
-// let mut vector_store = Arc::new(Mutex::new(VectorStore::new()));
+/// Most stores kept open at once. Each holds its whole graph in memory, so
+/// beyond this the least recently used are written back and closed.
+const MAX_OPEN_STORES: usize = 8;
+
+struct OpenStore {
+    store: Arc<Mutex<VectorStore>>,
+    last_used: Instant,
+}
+
+type StoreRegistry = Mutex<HashMap<PathBuf, OpenStore>>;
 
-static VECTOR_STORE: OnceLock<Arc<Mutex<VectorStore>>> = OnceLock::new();
+/// Every open vector store, keyed by its location on disk. Stores are named
+/// (one per book, e.g. `{book_id}-vectordb`, plus any shared index) and each
+/// has its own lock, so ingesting one book never blocks searching another.
+static VECTOR_STORES: OnceLock<StoreRegistry> = OnceLock::new();
 
-pub fn vector_store() -> &'static Arc<Mutex<VectorStore>> {
-    VECTOR_STORE.get_or_init(|| {
-        Arc::new(Mutex::new(
-            VectorStore::new().expect("Failed to create VectorStore"),
-        ))
-    })
+fn registry() -> &'static StoreRegistry {
+    VECTOR_STORES.get_or_init(|| Mutex::new(HashMap::new()))
 }
 
-fn init_vector_store(
+/// Flush and close the least recently used of `stores` until at most `max`
+/// are open. Stores in use elsewhere, or that fail to flush, stay open.
+fn close_least_recently_used(stores: &mut HashMap<PathBuf, OpenStore>, max: usize) {
+    // Handles are only cloned under the registry lock, so an unshared
+    // store can't be picked up while it is being closed.
+    let mut idle = stores
+        .iter()
+        .filter(|(_, open)| Arc::strong_count(&open.store) == 1)
+        .map(|(key, open)| (open.last_used, key.clone()))
+        .collect::<Vec<_>>();
+    idle.sort();
+    for (_, key) in idle {
+        if stores.len() <= max {
+            break;
+        }
+        let flushed = match stores[&key].store.try_lock() {
+            Ok(mut vectorstore) => vectorstore.flush(),
+            Err(_) => continue,
+        };
+        match flushed {
+            Ok(()) => {
+                stores.remove(&key);
+            }
+            Err(e) => eprintln!("Failed to flush vector store {}: {}", key.display(), e),
+        }
+    }
+}
+
+/// Why an index described by `manifest` can't serve embeddings of `dim`
+/// dimensions from `model_id`, if it can't.
+fn reindex_reason(manifest: &IndexManifest, dim: usize, model_id: Option<&str>) -> Option<String> {
+    if let (Some(indexed), Some(current)) = (manifest.model_id.as_deref(), model_id) {
+        if indexed != current {
+            return Some(format!(
+                "built with {}, but the embedding model is now {}",
+                indexed, current
+            ));
+        }
+    }
+    if manifest.dim != dim {
+        return Some(format!(
+            "built with {}-dimensional vectors, but embeddings now have {}",
+            manifest.dim, dim
+        ));
+    }
+    None
+}
+
+/// Whether the index named `name` was built by a different embedding model
+/// than `model_id`. Indexes without a manifest predate model tracking and
+/// are assumed compatible.
+pub fn index_needs_reindex(
+    app_data_dir: &Path,
+    name: &str,
+    model_id: &str,
+) -> anyhow::Result<bool> {
+    let manifest = match VectorStore::read_manifest(app_data_dir, name)? {
+        Some(manifest) => manifest,
+        None => return Ok(false),
+    };
+    Ok(manifest
+        .model_id
+        .as_deref()
+        .is_some_and(|indexed| indexed != model_id))
+}
+
+/// Get the store named `name` in `app_data_dir`, opening it on first use.
+pub fn vector_store(
     app_data_dir: PathBuf,
     dim: usize,
     name: &str,
-) -> anyhow::Result<MutexGuard<'static, VectorStore>> {
-    let mut vectorstore = vector_store()
+    config: &IndexConfig,
+) -> anyhow::Result<Arc<Mutex<VectorStore>>> {
+    let mut stores = registry()
         .lock()
-        .map_err(|e| anyhow::anyhow!("Failed to lock vector store: {}", e))?;
-    vectorstore
-        .init(app_data_dir, dim, name)
-        .map_err(|e| anyhow::anyhow!("Failed to initialize vector store: {}", e))?;
-    Ok(vectorstore)
+        .map_err(|e| anyhow::anyhow!("Failed to lock vector store registry: {}", e))?;
+    let key = app_data_dir.join(name);
+    if let Some(open) = stores.get_mut(&key) {
+        open.last_used = Instant::now();
+        let store = &open.store;
+        let mut vectorstore = lock_store(store)?;
+        if let Some(requested) = config.metric {
+            if vectorstore.metric != requested {
+                anyhow::bail!(
+                    "Index {} uses the {:?} metric, not {:?}",
+                    name,
+                    vectorstore.metric,
+                    requested
+                );
+            }
+        }
+        if vectorstore.model_id.is_none() {
+            vectorstore.model_id = config.model_id.clone();
+        }
+        return Ok(store.clone());
+    }
+    let store = Arc::new(Mutex::new(
+        VectorStore::open(app_data_dir, dim, name, config)
+            .map_err(|e| anyhow::anyhow!("Failed to initialize vector store: {}", e))?,
+    ));
+    stores.insert(
+        key,
+        OpenStore {
+            store: store.clone(),
+            last_used: Instant::now(),
+        },
+    );
+    close_least_recently_used(&mut stores, MAX_OPEN_STORES);
+    Ok(store)
 }
+
+fn lock_store(store: &Mutex<VectorStore>) -> anyhow::Result<MutexGuard<'_, VectorStore>> {
+    store
+        .lock()
+        .map_err(|e| anyhow::anyhow!("Failed to lock vector store: {}", e))
+}
+
 pub fn save_vectors(
     vectors: Vec<Vector>,
     app_data_dir: PathBuf,
     dim: usize,
     name: &str,
+    config: &IndexConfig,
 ) -> anyhow::Result<()> {
-    let mut vectorstore = init_vector_store(app_data_dir, dim, name)?;
+    let store = vector_store(app_data_dir, dim, name, config)?;
+    let mut vectorstore = lock_store(&store)?;
 
     vectorstore
         .add_vectors(vectors)
@@ -266,11 +806,412 @@ pub fn search_vectors(
     name: &str,
     query: Vec<f32>,
     k: usize,
+    config: &IndexConfig,
 ) -> anyhow::Result<Vec<SearchResult>> {
-    let vectorstore = init_vector_store(app_data_dir, dim, name)?;
+    search_vectors_filtered(app_data_dir, dim, name, query, k, config, None)
+}
+
+/// [`search_vectors`] restricted to the ids in `allowed`, if given.
+pub fn search_vectors_filtered(
+    app_data_dir: PathBuf,
+    dim: usize,
+    name: &str,
+    query: Vec<f32>,
+    k: usize,
+    config: &IndexConfig,
+    allowed: Option<&HashSet<u64>>,
+) -> anyhow::Result<Vec<SearchResult>> {
+    let store = vector_store(app_data_dir, dim, name, config)?;
+    let mut vectorstore = lock_store(&store)?;
 
     let res = vectorstore
-        .search(query, k)
+        .search_filtered(query, k, allowed)
         .map_err(|e| anyhow::anyhow!("Failed to search vectors: {}", e))?;
     Ok(res)
 }
+
+/// Remove the vectors with `ids` from the store named `name`, such as
+/// those of a page's chunks that were replaced when it was indexed again.
+pub fn remove_vectors(
+    app_data_dir: PathBuf,
+    dim: usize,
+    name: &str,
+    ids: &[u64],
+    config: &IndexConfig,
+) -> anyhow::Result<()> {
+    let store = vector_store(app_data_dir, dim, name, config)?;
+    let mut vectorstore = lock_store(&store)?;
+
+    vectorstore
+        .remove_ids(ids)
+        .map_err(|e| anyhow::anyhow!("Failed to remove vectors: {}", e))
+}
+
+/// Close the store named `name` and delete its index files.
+pub fn delete_vector_store(app_data_dir: PathBuf, name: &str) -> anyhow::Result<()> {
+    let key = app_data_dir.join(name);
+    let open_store = registry()
+        .lock()
+        .map_err(|e| anyhow::anyhow!("Failed to lock vector store registry: {}", e))?
+        .remove(&key);
+    match open_store {
+        // Wait for any in-flight operation on the store before deleting it.
+        Some(open) => lock_store(&open.store)?.destroy(),
+        None => VectorStore::remove_dump(&app_data_dir, name),
+    }
+}
+
+/// Write the store named `name` to disk if it is open and has been modified.
+pub fn flush_vector_store(app_data_dir: &Path, name: &str) -> anyhow::Result<()> {
+    let open_store = registry()
+        .lock()
+        .map_err(|e| anyhow::anyhow!("Failed to lock vector store registry: {}", e))?
+        .get(&app_data_dir.join(name))
+        .map(|open| open.store.clone());
+    match open_store {
+        Some(store) => lock_store(&store)?.flush(),
+        None => Ok(()),
+    }
+}
+
+/// Write all modified in-memory indexes to disk. Every store is tried, so
+/// one that can't be written doesn't keep the others in memory.
+pub fn flush_vectors() -> anyhow::Result<()> {
+    // Snapshot the registry so stores are flushed without holding its lock.
+    let stores = registry()
+        .lock()
+        .map_err(|e| anyhow::anyhow!("Failed to lock vector store registry: {}", e))?
+        .iter()
+        .map(|(key, open)| (key.clone(), open.store.clone()))
+        .collect::<Vec<_>>();
+    let errors = stores
+        .into_iter()
+        .filter_map(|(key, store)| {
+            let flushed = lock_store(&store).and_then(|mut vectorstore| vectorstore.flush());
+            flushed.err().map(|e| format!("{}: {}", key.display(), e))
+        })
+        .collect::<Vec<_>>();
+    if !errors.is_empty() {
+        anyhow::bail!(
+            "Failed to flush {} vector store(s): {}",
+            errors.len(),
+            errors.join("; ")
+        );
+    }
+    Ok(())
+}
+
+/// Flush modified indexes every [`FLUSH_INTERVAL`] so an idle index does not
+/// stay dirty until the app exits.
+pub async fn flush_periodically() {
+    let mut interval = tokio::time::interval(FLUSH_INTERVAL);
+    loop {
+        interval.tick().await;
+        match tokio::task::spawn_blocking(flush_vectors).await {
+            Ok(Err(e)) => eprintln!("Failed to flush vector indexes: {}", e),
+            Err(e) => eprintln!("Vector flush task failed: {}", e),
+            Ok(Ok(())) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use expectest::prelude::*;
+    use tempfile::TempDir;
+
+    fn unit_vector(dim: usize, axis: usize) -> Vec<f32> {
+        let mut vector = vec![0.0; dim];
+        vector[axis] = 1.0;
+        vector
+    }
+
+    #[test]
+    fn test_named_stores_are_independent() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().to_path_buf();
+        let dim = 4;
+
+        save_vectors(
+            vec![Vector::new(1, unit_vector(dim, 0))],
+            dir.clone(),
+            dim,
+            "3-vectordb",
+            &IndexConfig::default(),
+        )?;
+        save_vectors(
+            vec![Vector::new(2, unit_vector(dim, 1))],
+            dir.clone(),
+            dim,
+            "7-vectordb",
+            &IndexConfig::default(),
+        )?;
+
+        let config = IndexConfig::default();
+        let book_3 = search_vectors(
+            dir.clone(),
+            dim,
+            "3-vectordb",
+            unit_vector(dim, 1),
+            5,
+            &config,
+        )?;
+        let book_7 = search_vectors(dir, dim, "7-vectordb", unit_vector(dim, 0), 5, &config)?;
+        assert_eq!(book_3.iter().map(|r| r.id).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(book_7.iter().map(|r| r.id).collect::<Vec<_>>(), vec![2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_flushed_index_reloads_from_disk() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().to_path_buf();
+        let dim = 4;
+
+        let mut store = VectorStore::new(dir.clone(), dim, "reload-vectordb")?;
+        store.add_vectors(vec![
+            Vector::new(10, unit_vector(dim, 0)),
+            Vector::new(11, unit_vector(dim, 2)),
+        ])?;
+        store.flush()?;
+        assert!(VectorStore::data_file_exists(&dir, "reload-vectordb"));
+
+        let mut reloaded = VectorStore::new(dir, dim, "reload-vectordb")?;
+        let results = reloaded.search(unit_vector(dim, 2), 1)?;
+        assert_eq!(results[0].id, 11);
+        Ok(())
+    }
+
+    #[test]
+    fn test_removed_ids_are_not_returned() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dim = 4;
+        let mut store = VectorStore::new(temp_dir.path().to_path_buf(), dim, "remove-vectordb")?;
+        store.add_vectors(vec![
+            Vector::new(1, unit_vector(dim, 0)),
+            Vector::new(2, unit_vector(dim, 1)),
+        ])?;
+
+        store.remove_ids(&[1])?;
+        let results = store.search(unit_vector(dim, 0), 2)?;
+        assert_eq!(results.iter().map(|r| r.id).collect::<Vec<_>>(), vec![2]);
+
+        store.flush()?;
+        let results = store.search(unit_vector(dim, 0), 2)?;
+        assert_eq!(results.iter().map(|r| r.id).collect::<Vec<_>>(), vec![2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_filtered_search_only_returns_allowed_ids() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dim = 4;
+        let mut store = VectorStore::new(temp_dir.path().to_path_buf(), dim, "filter-vectordb")?;
+        store.add_vectors(vec![
+            Vector::new(1, unit_vector(dim, 0)),
+            Vector::new(2, unit_vector(dim, 1)),
+            Vector::new(3, unit_vector(dim, 2)),
+        ])?;
+        store.remove_ids(&[3])?;
+
+        let allowed = HashSet::from([2, 3]);
+        let results = store.search_filtered(unit_vector(dim, 0), 3, Some(&allowed))?;
+
+        assert_eq!(results.iter().map(|r| r.id).collect::<Vec<_>>(), vec![2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_re_adding_an_id_replaces_its_vector() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dim = 4;
+        let mut store = VectorStore::new(temp_dir.path().to_path_buf(), dim, "replace-vectordb")?;
+        store.add_vectors(vec![
+            Vector::new(1, unit_vector(dim, 0)),
+            Vector::new(2, unit_vector(dim, 1)),
+        ])?;
+        store.add_vectors(vec![Vector::new(1, unit_vector(dim, 2))])?;
+
+        let results = store.search(unit_vector(dim, 2), 3)?;
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].id, 1);
+        assert_eq!(results[0].distance, 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replaced_vectors_are_tombstoned_until_flush() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().to_path_buf();
+        let dim = 8;
+        let mut store = VectorStore::new(dir.clone(), dim, "tombstone-vectordb")?;
+        store.add_vectors(
+            (0..8)
+                .map(|i| Vector::new(i, unit_vector(dim, i as usize)))
+                .collect(),
+        )?;
+        store.add_vectors(vec![Vector::new(1, unit_vector(dim, 3))])?;
+        store.add_vectors(vec![Vector::new(1, unit_vector(dim, 2))])?;
+
+        let index = store.index.as_ref().unwrap();
+        expect!(index.tombstones.len()).to(be_equal_to(2));
+        expect!(index.hnsw.len()).to(be_equal_to(10));
+
+        let results = store.search(unit_vector(dim, 2), 4)?;
+        let ids = results.iter().map(|r| r.id).collect::<Vec<_>>();
+        expect!(ids.iter().filter(|id| **id == 1).count()).to(be_equal_to(1));
+        expect!(ids.len()).to(be_equal_to(4));
+        let allowed = HashSet::from([1]);
+        let results = store.search_filtered(unit_vector(dim, 2), 4, Some(&allowed))?;
+        expect!(results.len()).to(be_equal_to(1));
+        expect!(results[0].distance).to(be_close_to(0.0));
+
+        store.flush()?;
+        expect!(store.index.as_ref().unwrap().tombstones.len()).to(be_equal_to(0));
+        let mut reloaded = VectorStore::new(dir, dim, "tombstone-vectordb")?;
+        let results = reloaded.search_filtered(unit_vector(dim, 2), 4, Some(&allowed))?;
+        expect!(results.len()).to(be_equal_to(1));
+        expect!(results[0].distance).to(be_close_to(0.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_metric_is_recorded_and_reused_on_reload() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().to_path_buf();
+        let dim = 4;
+
+        let dot = IndexConfig {
+            metric: Some(Metric::Dot),
+            ..Default::default()
+        };
+        let mut store = VectorStore::open(dir.clone(), dim, "dot-vectordb", &dot)?;
+        store.add_vectors(vec![
+            Vector::new(1, unit_vector(dim, 0)),
+            Vector::new(2, unit_vector(dim, 3)),
+        ])?;
+        store.flush()?;
+
+        let mut reloaded = VectorStore::new(dir.clone(), dim, "dot-vectordb")?;
+        expect!(reloaded.metric).to(be_equal_to(Metric::Dot));
+        let results = reloaded.search(unit_vector(dim, 3), 1)?;
+        expect!(results[0].id).to(be_equal_to(2));
+        expect!(results[0].score).to(be_close_to(1.0));
+
+        let l2 = IndexConfig {
+            metric: Some(Metric::L2),
+            ..Default::default()
+        };
+        let mismatched = VectorStore::open(dir, dim, "dot-vectordb", &l2);
+        expect!(mismatched.is_err()).to(be_equal_to(true));
+        Ok(())
+    }
+
+    #[test]
+    fn test_manifest_flags_indexes_from_another_model() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().to_path_buf();
+        let dim = 4;
+        let config = |model_id: &str| IndexConfig {
+            metric: None,
+            model_id: Some(model_id.to_string()),
+        };
+
+        let mut store = VectorStore::open(dir.clone(), dim, "model-vectordb", &config("old"))?;
+        store.add_vectors(vec![Vector::new(1, unit_vector(dim, 0))])?;
+        store.flush()?;
+
+        let manifest = VectorStore::read_manifest(&dir, "model-vectordb")?.unwrap();
+        expect!(manifest.schema_version).to(be_equal_to(MANIFEST_SCHEMA_VERSION));
+        expect!(manifest.model_id.as_deref()).to(be_equal_to(Some("old")));
+        expect!(manifest.dim).to(be_equal_to(dim));
+        expect!(manifest.metric).to(be_equal_to(Metric::Cosine));
+        expect!(manifest.vector_count).to(be_equal_to(1));
+
+        expect!(index_needs_reindex(&dir, "model-vectordb", "old")?).to(be_equal_to(false));
+        expect!(index_needs_reindex(&dir, "model-vectordb", "new")?).to(be_equal_to(true));
+
+        let mut reopened = VectorStore::open(dir.clone(), dim, "model-vectordb", &config("new"))?;
+        expect!(reopened.needs_reindex()).to(be_equal_to(true));
+        expect!(reopened.search(unit_vector(dim, 0), 1).is_err()).to(be_equal_to(true));
+
+        let mut wrong_dim = VectorStore::open(dir, 8, "model-vectordb", &config("old"))?;
+        expect!(wrong_dim.needs_reindex()).to(be_equal_to(true));
+        expect!(wrong_dim.search(vec![0.0; 8], 1).is_err()).to(be_equal_to(true));
+        Ok(())
+    }
+
+    #[test]
+    fn test_scores_are_similarities() {
+        expect!(Metric::Cosine.similarity(0.0)).to(be_close_to(1.0));
+        expect!(Metric::Cosine.similarity(2.0)).to(be_close_to(-1.0));
+        expect!(Metric::L2.similarity(0.0)).to(be_close_to(1.0));
+        expect!(Metric::L2.similarity(1.0)).to(be_close_to(0.5));
+        expect!(Metric::Dot.similarity(0.25)).to(be_close_to(0.75));
+    }
+
+    #[test]
+    fn test_least_recently_used_stores_are_flushed_and_closed() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().to_path_buf();
+        let dim = 4;
+        let start = Instant::now();
+        let mut stores = HashMap::new();
+        for (age, name) in ["oldest-vectordb", "older-vectordb", "newest-vectordb"]
+            .into_iter()
+            .enumerate()
+        {
+            let mut store = VectorStore::open(dir.clone(), dim, name, &IndexConfig::default())?;
+            store.add_vectors(vec![Vector::new(1, unit_vector(dim, 0))])?;
+            let open = OpenStore {
+                store: Arc::new(Mutex::new(store)),
+                last_used: start + Duration::from_secs(age as u64),
+            };
+            stores.insert(dir.join(name), open);
+        }
+
+        // The oldest store is in use, so the next oldest goes instead.
+        let in_use = stores[&dir.join("oldest-vectordb")].store.clone();
+        close_least_recently_used(&mut stores, 2);
+        expect!(stores.contains_key(&dir.join("older-vectordb"))).to(be_false());
+        assert!(VectorStore::data_file_exists(&dir, "older-vectordb"));
+        assert!(!VectorStore::data_file_exists(&dir, "oldest-vectordb"));
+
+        drop(in_use);
+        close_least_recently_used(&mut stores, 1);
+        let open: Vec<&PathBuf> = stores.keys().collect();
+        expect!(open).to(be_equal_to(vec![&dir.join("newest-vectordb")]));
+        assert!(VectorStore::data_file_exists(&dir, "oldest-vectordb"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_vector_store_removes_files() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path().to_path_buf();
+        let dim = 4;
+        save_vectors(
+            vec![Vector::new(1, unit_vector(dim, 0))],
+            dir.clone(),
+            dim,
+            "deleted-vectordb",
+            &IndexConfig::default(),
+        )?;
+        flush_vector_store(&dir, "deleted-vectordb")?;
+        assert!(VectorStore::data_file_exists(&dir, "deleted-vectordb"));
+
+        delete_vector_store(dir.clone(), "deleted-vectordb")?;
+        assert!(!VectorStore::data_file_exists(&dir, "deleted-vectordb"));
+        let results = search_vectors(
+            dir,
+            dim,
+            "deleted-vectordb",
+            unit_vector(dim, 0),
+            1,
+            &IndexConfig::default(),
+        )?;
+        assert!(results.is_empty());
+        Ok(())
+    }
+}
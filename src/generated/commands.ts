@@ -21,7 +21,7 @@ export async function pollForUser(params: types.PollForUserParams): Promise<type
   return invoke('poll_for_user', params);
 }
 
-export async function getContextForQuery(params: types.GetContextForQueryParams): Promise<types.string[]> {
+export async function getContextForQuery(params: types.GetContextForQueryParams): Promise<types.RetrievedPassage[]> {
   return invoke('get_context_for_query', params);
 }
 
@@ -109,3 +109,95 @@ export async function getTextFromVectorId(params: types.GetTextFromVectorIdParam
   return invoke('get_text_from_vector_id', params);
 }
 
+export async function getEmbeddingModelStatus(): Promise<types.ModelStatus> {
+  return invoke('get_embedding_model_status');
+}
+
+export async function flushVectors(): Promise<void> {
+  return invoke('flush_vectors');
+}
+
+export async function bookNeedsReindex(params: types.BookNeedsReindexParams): Promise<boolean> {
+  return invoke('book_needs_reindex', params);
+}
+
+export async function reindexBook(params: types.ReindexBookParams): Promise<void> {
+  return invoke('reindex_book', params);
+}
+
+export async function answerQuestion(params: types.AnswerQuestionParams): Promise<types.CitedAnswer> {
+  return invoke('answer_question', params);
+}
+
+export async function streamAnswer(params: types.StreamAnswerParams): Promise<void> {
+  return invoke('stream_answer', params);
+}
+
+export async function cancelAnswerStream(params: types.CancelAnswerStreamParams): Promise<boolean> {
+  return invoke('cancel_answer_stream', params);
+}
+
+export async function askInConversation(params: types.AskInConversationParams): Promise<types.CitedAnswer> {
+  return invoke('ask_in_conversation', params);
+}
+
+export async function startConversation(params: types.StartConversationParams): Promise<types.Conversation> {
+  return invoke('start_conversation', params);
+}
+
+export async function listConversations(params: types.ListConversationsParams): Promise<types.Conversation[]> {
+  return invoke('list_conversations', params);
+}
+
+export async function getConversationMessages(params: types.GetConversationMessagesParams): Promise<types.Message[]> {
+  return invoke('get_conversation_messages', params);
+}
+
+export async function deleteConversation(params: types.DeleteConversationParams): Promise<void> {
+  return invoke('delete_conversation', params);
+}
+
+export async function getSettings(): Promise<types.Settings> {
+  return invoke('get_settings');
+}
+
+export async function setSettings(params: types.SetSettingsParams): Promise<string[]> {
+  return invoke('set_settings', params);
+}
+
+export async function indexBookText(params: types.IndexBookTextParams): Promise<void> {
+  return invoke('index_book_text', params);
+}
+
+export async function getBookToc(params: types.GetBookTocParams): Promise<types.TocEntry[]> {
+  return invoke('get_book_toc', params);
+}
+
+export async function enqueueIngest(params: types.EnqueueIngestParams): Promise<types.IngestJob> {
+  return invoke('enqueue_ingest', params);
+}
+
+export async function pauseIngest(params: types.PauseIngestParams): Promise<types.IngestJob> {
+  return invoke('pause_ingest', params);
+}
+
+export async function resumeIngest(params: types.ResumeIngestParams): Promise<types.IngestJob> {
+  return invoke('resume_ingest', params);
+}
+
+export async function cancelIngest(params: types.CancelIngestParams): Promise<types.IngestJob> {
+  return invoke('cancel_ingest', params);
+}
+
+export async function getIngestJobs(): Promise<types.IngestJob[]> {
+  return invoke('get_ingest_jobs');
+}
+
+export async function importFolder(params: types.ImportFolderParams): Promise<types.ImportSummary> {
+  return invoke('import_folder', params);
+}
+
+export async function getMissingBooks(): Promise<number[]> {
+  return invoke('get_missing_books');
+}
+
@@ -5,6 +5,8 @@
  * Do not edit manually - regenerate using: cargo tauri-typegen generate
  */
 
+import type { Channel } from '@tauri-apps/api/core';
+
 export interface GetBookDataParams {
   path: Path;
   [key: string]: unknown;
@@ -27,6 +29,7 @@ export interface GetContextForQueryParams {
   queryText: string;
   bookId: number;
   k: number;
+  spoilerSafe?: boolean | null;
   [key: string]: unknown;
 }
 
@@ -34,6 +37,7 @@ export interface SaveVectorsParams {
   name: string;
   dim: number;
   vectors: Vector[];
+  metric?: Metric | null;
   [key: string]: unknown;
 }
 
@@ -113,6 +117,109 @@ export interface GetTextFromVectorIdParams {
   [key: string]: unknown;
 }
 
+export interface BookNeedsReindexParams {
+  bookId: number;
+  [key: string]: unknown;
+}
+
+export interface ReindexBookParams {
+  bookId: number;
+  [key: string]: unknown;
+}
+
+export interface AnswerQuestionParams {
+  question: string;
+  bookId: number;
+  k?: number | null;
+  spoilerSafe?: boolean | null;
+  [key: string]: unknown;
+}
+
+export interface StreamAnswerParams {
+  sessionId: string;
+  question: string;
+  bookId: number;
+  conversationId?: number | null;
+  k?: number | null;
+  spoilerSafe?: boolean | null;
+  onEvent: Channel<AnswerStreamEvent>;
+  [key: string]: unknown;
+}
+
+export interface CancelAnswerStreamParams {
+  sessionId: string;
+  [key: string]: unknown;
+}
+
+export interface AskInConversationParams {
+  conversationId: number;
+  question: string;
+  k?: number | null;
+  spoilerSafe?: boolean | null;
+  [key: string]: unknown;
+}
+
+export interface StartConversationParams {
+  bookId: number;
+  [key: string]: unknown;
+}
+
+export interface ListConversationsParams {
+  bookId: number;
+  [key: string]: unknown;
+}
+
+export interface GetConversationMessagesParams {
+  conversationId: number;
+  [key: string]: unknown;
+}
+
+export interface DeleteConversationParams {
+  conversationId: number;
+  [key: string]: unknown;
+}
+
+export interface SetSettingsParams {
+  settings: Settings;
+  [key: string]: unknown;
+}
+
+export interface IndexBookTextParams {
+  bookId: number;
+  [key: string]: unknown;
+}
+
+export interface GetBookTocParams {
+  bookId: number;
+  [key: string]: unknown;
+}
+
+export interface EnqueueIngestParams {
+  bookId: number;
+  [key: string]: unknown;
+}
+
+export interface PauseIngestParams {
+  bookId: number;
+  [key: string]: unknown;
+}
+
+export interface ResumeIngestParams {
+  bookId: number;
+  [key: string]: unknown;
+}
+
+export interface CancelIngestParams {
+  bookId: number;
+  [key: string]: unknown;
+}
+
+export interface ImportFolderParams {
+  dir: string;
+  onEvent: Channel<LibraryEvent>;
+  [key: string]: unknown;
+}
+
 export interface Vector {
   id: number;
   vector: number[];
@@ -123,6 +230,10 @@ export interface PageData {
   pageNumber: number;
   bookId: number;
   data: string;
+  chapterId?: number | null;
+  href?: string | null;
+  startOffset?: number | null;
+  endOffset?: number | null;
 }
 
 export interface BookData {
@@ -136,11 +247,14 @@ export interface BookData {
   location: string;
   coverKind?: string | null;
   version: number;
+  identifier?: string | null;
+  metadata: BookMetadata;
 }
 
 export interface SearchResult {
   id: number;
   distance: number;
+  score: number;
 }
 
 export interface EmbedResult {
@@ -166,12 +280,18 @@ export interface BookInsertable {
   location: string;
   coverKind: string;
   version: number;
+  contentHash?: string | null;
+  identifier?: string | null;
 }
 
 export interface Metadata {
   id: number;
   pageNumber: number;
   bookId: number;
+  chapterId?: number | null;
+  href?: string | null;
+  start?: number | null;
+  end?: number | null;
 }
 
 export interface User {
@@ -197,6 +317,9 @@ export interface Book {
   location: string;
   coverKind: string;
   version: number;
+  contentHash?: string | null;
+  identifier?: string | null;
+  metadata: BookMetadata;
 }
 
 export interface ChunkDataInsertable {
@@ -204,5 +327,130 @@ export interface ChunkDataInsertable {
   pageNumber: number;
   bookId: number;
   data: string;
+  chapterId?: number | null;
+  href?: string | null;
+  startOffset?: number | null;
+  endOffset?: number | null;
+}
+
+export type ModelStatus =
+  | { status: 'notLoaded' }
+  | { status: 'loading' }
+  | { status: 'ready' }
+  | { status: 'failed'; error: string };
+
+export type Metric = 'l2' | 'cosine' | 'dot';
+
+export interface RetrievedPassage {
+  chunkId: number;
+  bookId: number;
+  pageNumber: number;
+  text: string;
+  score: number;
+  distance?: number | null;
+  chapterId?: number | null;
+  href?: string | null;
+  startOffset?: number | null;
+  endOffset?: number | null;
+}
+
+export interface Citation {
+  passage: number;
+  chunkId: number;
+  pageNumber: number;
+  start: number;
+  end: number;
+}
+
+export interface CitedAnswer {
+  answer: string;
+  citations: Citation[];
+  passages: RetrievedPassage[];
+}
+
+export type AnswerStreamEvent =
+  | { event: 'passages'; data: RetrievedPassage[] }
+  | { event: 'token'; data: string }
+  | { event: 'done'; data: CitedAnswer }
+  | { event: 'error'; data: string }
+  | { event: 'cancelled' };
+
+export type Role = 'system' | 'user' | 'assistant';
+
+export interface Conversation {
+  id: number;
+  bookId: number;
+  title: string;
+}
+
+export interface Message {
+  id: number;
+  conversationId: number;
+  role: Role;
+  content: string;
+  citations: Citation[];
+}
+
+export interface Settings {
+  llm: LlmSettings;
+  backend: BackendSettings;
+  watchedFolders: string[];
+}
+
+export type LlmSettings =
+  | { kind: 'worker' }
+  | { kind: 'openAiCompatible'; baseUrl: string; model: string; apiKey?: string | null }
+  | { kind: 'mock' };
+
+export interface BackendSettings {
+  baseUrl?: string | null;
+  authToken?: string | null;
+  maxRetries: number;
+  retryBackoffMs: number;
+}
+
+export interface TocEntry {
+  title: string;
+  pageNumber?: number | null;
+  href?: string | null;
+  children: TocEntry[];
+}
+
+export type IngestStatus = 'queued' | 'running' | 'paused' | 'done' | 'failed' | 'cancelled';
+
+export interface IngestJob {
+  bookId: number;
+  status: IngestStatus;
+  totalPages: number;
+  donePages: number;
+  error?: string | null;
+}
+
+export type LibraryEvent =
+  | { event: 'found'; data: number }
+  | { event: 'imported'; data: Book }
+  | { event: 'failed'; data: { path: string; error: string } }
+  | { event: 'missing'; data: number[] }
+  | { event: 'done'; data: ImportSummary };
+
+export interface ImportSummary {
+  imported: number;
+  failed: number;
+}
+
+export interface BookMetadata {
+  language?: string | null;
+  identifiers: string[];
+  description?: string | null;
+  subjects: string[];
+  series?: string | null;
+  seriesIndex?: number | null;
+  published?: string | null;
+  contributors: Contributor[];
+}
+
+export interface Contributor {
+  name: string;
+  role?: string | null;
 }
 
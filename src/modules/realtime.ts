@@ -49,12 +49,12 @@ export async function startRealtime(bookId: number) {
       queryText: z.string(),
     }),
     execute: async ({ queryText }) => {
-      const context = await getContextForQuery({
+      const passages = await getContextForQuery({
         bookId,
         queryText,
         k: 3,
       });
-      return context;
+      return passages.map((passage) => passage.text);
     },
   });
 